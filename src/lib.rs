@@ -1,18 +1,323 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror, token, Address, Env, String, Symbol,
-    symbol_short, Vec, Map
+    contract, contractclient, contractimpl, contracttype, contracterror, token, Address, Bytes, BytesN, Env, String, Symbol,
+    symbol_short, xdr::ToXdr, Vec
 };
 
+const BPS_DENOMINATOR: i128 = 10_000;
+const MAX_FEE_BPS: u32 = 10_000;
+const MAX_PERFORMANCE_FEE_BPS: u32 = 3_000;
+// ~5s ledgers
+const LEDGERS_PER_YEAR: i128 = 17_280 * 365;
+const MAX_DECIMALS: u32 = 36;
+// Most accounts `balances_of` reads in one call
+const MAX_BALANCE_QUERY: u32 = 100;
+// Passed as `decimals` to `initialize` to use the asset's own decimals
+const DECIMALS_FROM_ASSET: u32 = u32::MAX;
+// Longest asset name or symbol `initialize_auto` will prefix; longer ones get the defaults
+const MAX_DERIVED_METADATA_LEN: usize = 64;
+// Shares locked in the vault's own balance on the first deposit when dead shares are enabled
+const MINIMUM_LIQUIDITY: i128 = 1_000;
+const DEFAULT_CHECKPOINT_WINDOW: u32 = 100;
+// Separate receipts tracked per account for the cooldown; beyond this a receipt joins the newest
+const MAX_COOLDOWN_LOTS: u32 = 8;
+// Nonces live in persistent storage and are bumped to ~30 days whenever they drop below ~15
+const NONCE_TTL_THRESHOLD: u32 = 17_280 * 15;
+const NONCE_TTL_EXTEND_TO: u32 = 17_280 * 30;
+// Balance and supply history is bumped the same way each time it is written
+const HISTORY_TTL_THRESHOLD: u32 = 17_280 * 15;
+const HISTORY_TTL_EXTEND_TO: u32 = 17_280 * 30;
+// Roles the admin can grant; the admin holds every role implicitly
+const PAUSER: Symbol = symbol_short!("PAUSER");
+const FEE_MANAGER: Symbol = symbol_short!("FEE_MGR");
+const REPORTER: Symbol = symbol_short!("REPORTER");
+// First topic of every event. Bumped whenever any event's topics or data change shape, so
+// indexers can tell schemas apart instead of misreading them.
+const EVENT_VERSION: Symbol = symbol_short!("v1");
+// Storage layout version written by this code; `migrate` brings older layouts up to it
+const CONTRACT_VERSION: u32 = 2;
+
+// Admin, Asset, Name, Symbol, Decimals, DecimalsOffset and the entry/exit fee keys are only
+// read from vaults older than layout version 2, which keep them in `Config` instead
 #[contracttype]
 pub enum DataKey {
+    Admin,
     Asset,
     Name,
     Symbol,
     Decimals,
+    DecimalsOffset,
     TotalSupply,
     Balance(Address),
     Allowance(Address, Address),
+    Paused,
+    EntryFeeBps,
+    ExitFeeBps,
+    FeeRecipient,
+    DepositCap,
+    ReentrancyGuard,
+    UserDepositCap,
+    UserDeposited(Address),
+    CooldownLedgers,
+    DeadShares,
+    FirstDeposit,
+    DeployedAssets,
+    Strategy,
+    Checkpoint(u32),
+    CheckpointCount,
+    CheckpointWindow,
+    Nonce(Address),
+    WithdrawRequest(u32),
+    WithdrawRequestCount,
+    PendingWithdrawals,
+    MinSolvencyBps,
+    AssetEntry(Address),
+    AssetList,
+    Oracle,
+    HighWaterMark,
+    PerformanceFeeBps,
+    ManagementFeeBps,
+    LastFeeAccrual,
+    EventSeq,
+    UnbackedShares,
+    MaxUnbacked,
+    HolderIndex(u32),
+    HolderExists(Address),
+    HolderCount,
+    MinDeposit,
+    MinBalance,
+    RoundingMode,
+    TotalEntryFees,
+    TotalExitFees,
+    TotalPerfFees,
+}
+
+// `contracttype` enums are capped at 50 variants, which DataKey has reached
+#[contracttype]
+pub enum AccessKey {
+    PendingAdmin,
+    Role(Symbol, Address),
+    AllowlistEnabled,
+    Allowlisted(Address),
+    Blocked(Address),
+}
+
+#[contracttype]
+pub enum AccountingKey {
+    // `total_assets` as of the last vault call that moved assets; anything above it arrived
+    // directly and can be claimed with `deposit_from_balance`
+    LastKnownAssets,
+    // Balance and supply history for `balance_of_at` / `total_supply_at`, in persistent storage
+    BalanceCheckpoint(Address, u32),
+    BalanceCheckpointCount(Address),
+    SupplyCheckpoint(u32),
+    SupplyCheckpointCount,
+    // `Snapshot` of the smoothed assets backing one whole share, as of its last update
+    SmoothedAssets,
+    RebasingAsset,
+    // Value of holdings that can't be read on-chain, as last submitted through `report_assets`
+    ReportedAssets,
+    // `Snapshot` of `total_assets` when `sync_rebase` last booked a change
+    RebaseCheckpoint,
+    // Assets per whole share as of the last `rate_updated` event
+    LastRate,
+}
+
+#[contracttype]
+pub enum FeeKey {
+    FlashFeeBps,
+}
+
+#[contracttype]
+pub enum UpgradeKey {
+    Version,
+}
+
+#[contracttype]
+pub enum ConfigKey {
+    Config,
+}
+
+#[contracttype]
+pub enum GuardKey {
+    PriceSmoothingBps,
+    MaxReportDeltaBps,
+    MaxOutflowPerLedger,
+    // (total assets before the ledger's first withdrawal, assets withdrawn so far) in temporary storage
+    LedgerOutflow(u32),
+    Tripped,
+    Shutdown,
+    // (ledger received, shares) for each receipt still inside the cooldown, oldest first
+    CooldownLock(Address),
+}
+
+// How the `convert_to_*` views round; unset rounds down. Deposits, mints, withdrawals,
+// redemptions and their previews always round in the vault's favor whatever the mode:
+// down for deposit/redeem, up for mint/withdraw.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    HalfUp,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+// A value as of the end of `ledger`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub ledger: u32,
+    pub value: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    pub ledger: u32,
+    pub total_assets: i128,
+    pub total_supply: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaultInfo {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    pub asset: Address,
+    pub total_supply: i128,
+    pub total_assets: i128,
+    pub price_per_share: i128,
+}
+
+// Health snapshot for monitoring. `deposit_room` is what the global deposit cap still allows.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaultStatus {
+    pub paused: bool,
+    pub shutdown: bool,
+    pub solvent: bool,
+    pub deposit_room: i128,
+    pub idle_assets: i128,
+}
+
+// Settings read on almost every call, packed under one key so they cost a single storage read.
+// `decimals` is the asset's; share decimals add `decimals_offset`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    pub admin: Address,
+    pub asset: Address,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    pub decimals_offset: u32,
+    pub entry_fee_bps: u32,
+    pub exit_fee_bps: u32,
+}
+
+// Rates in basis points. Unset fees read as zero and the recipient falls back to the admin,
+// the same as the individual getters.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeConfig {
+    pub entry_bps: u32,
+    pub exit_bps: u32,
+    pub performance_bps: u32,
+    pub management_bps: u32,
+    pub flash_bps: u32,
+    pub recipient: Address,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepositEvent {
+    pub sender: Address,
+    pub owner: Address,
+    pub assets: i128,
+    pub shares: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawEvent {
+    pub sender: Address,
+    pub receiver: Address,
+    pub owner: Address,
+    pub assets: i128,
+    pub shares: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HarvestEvent {
+    pub profit: i128,
+    pub loss: i128,
+    pub total_assets_after: i128,
+}
+
+// `total_assets` marks deployed assets to the strategy's own report, while `implied_assets` is
+// what the outstanding shares convert to at the booked rate. A negative `gap` means the shares
+// claim more than the vault could realize.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackingReport {
+    pub total_assets: i128,
+    pub total_supply: i128,
+    pub implied_assets: i128,
+    pub gap: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepositSimulation {
+    pub shares: i128,
+    pub entry_fee: i128,
+    pub net_assets: i128,
+    pub new_price_per_share: i128,
+}
+
+// A queued exit: the shares are already burned and `assets` plus `fee` are owed
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawRequest {
+    pub owner: Address,
+    pub receiver: Address,
+    pub shares: i128,
+    pub assets: i128,
+    pub fee: i128,
+    pub ledger: u32,
+}
+
+// Interface the vault expects from a yield strategy. The vault transfers assets to the
+// strategy before calling `deposit`, and `withdraw` must send assets back to the vault.
+#[contractclient(name = "StrategyClient")]
+pub trait Strategy {
+    fn deposit(env: Env, amount: i128);
+    fn withdraw(env: Env, amount: i128);
+    fn report(env: Env) -> i128;
+}
+
+// Price feed used to report vault values in a quote unit such as USD. `price` is the value of
+// one unit of `asset` in quote units, scaled by 10^`decimals`.
+#[contractclient(name = "OracleClient")]
+pub trait Oracle {
+    fn price(env: Env, asset: Address) -> i128;
+    fn decimals(env: Env) -> u32;
+}
+
+// Callback for `flash_loan`. The vault sends `amount` to the borrower before calling
+// `on_flash_loan`, which must send `amount + fee` back to the vault before returning.
+#[contractclient(name = "FlashBorrowerClient")]
+pub trait FlashBorrower {
+    fn on_flash_loan(env: Env, asset: Address, amount: i128, fee: i128, data: Bytes);
 }
 
 #[contracterror]
@@ -24,6 +329,49 @@ pub enum Error {
     InsufficientBalance = 3,
     InsufficientAllowance = 4,
     InvalidAddress = 5,
+    Overflow = 6,
+    Paused = 7,
+    InvalidFee = 8,
+    SlippageExceeded = 9,
+    DepositCapExceeded = 10,
+    Reentrancy = 11,
+    InsufficientVaultAssets = 12,
+    AlreadyInitialized = 13,
+    InvalidDecimals = 14,
+    CannotSweepAsset = 15,
+    UserCapExceeded = 16,
+    Cooldown = 17,
+    InsufficientIdleAssets = 18,
+    StrategyNotSet = 19,
+    InvalidCheckpointWindow = 20,
+    SelfTransfer = 21,
+    PermitExpired = 22,
+    InvalidNonce = 23,
+    RequestNotFound = 24,
+    Insolvent = 25,
+    InvalidWeight = 26,
+    AssetNotRegistered = 27,
+    WouldViolateMinimum = 28,
+    UnbackedCapExceeded = 29,
+    BelowMinimum = 30,
+    NotInitialized = 31,
+    NoPendingAdmin = 32,
+    Unauthorized = 33,
+    FlashLoanNotRepaid = 34,
+    Shutdown = 35,
+    SupplyOverflow = 36,
+    NotAllowlisted = 37,
+    AddressBlocked = 38,
+    AlreadyMigrated = 39,
+    InvalidVersion = 40,
+    WouldEmptyVault = 41,
+    InvalidAmount = 42,
+    AssetMismatch = 43,
+    BatchTooLarge = 44,
+    ReportDeltaTooLarge = 45,
+    DeadlinePassed = 46,
+    ReportBoundNotSet = 47,
+    StrategyShortfall = 48,
 }
 
 #[contract]
@@ -31,352 +379,3022 @@ pub struct VaultContract;
 
 #[contractimpl]
 impl VaultContract {
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         env: Env,
+        admin: Address,
         asset: Address,
         name: String,
         symbol: String,
         decimals: u32,
+        decimals_offset: u32,
+        rounding_mode: Option<RoundingMode>,
     ) -> Result<(), Error> {
-        if env.storage().instance().has(&DataKey::Asset) {
-            return Err(Error::InvalidAddress);
+        if Self::is_initialized(env.clone()) {
+            return Err(Error::AlreadyInitialized);
+        }
+        
+        // Anything that can't answer `decimals` is not a token
+        let asset_client = token::Client::new(&env, &asset);
+        let asset_decimals = match asset_client.try_decimals() {
+            Ok(Ok(asset_decimals)) => asset_decimals,
+            _ => return Err(Error::InvalidAddress),
+        };
+        let decimals = if decimals == DECIMALS_FROM_ASSET {
+            asset_decimals
+        } else {
+            decimals
+        };
+        
+        if decimals.saturating_add(decimals_offset) > MAX_DECIMALS {
+            return Err(Error::InvalidDecimals);
         }
         
-        env.storage().instance().set(&DataKey::Asset, &asset);
-        env.storage().instance().set(&DataKey::Name, &name);
-        env.storage().instance().set(&DataKey::Symbol, &symbol);
-        env.storage().instance().set(&DataKey::Decimals, &decimals);
+        env.storage().instance().set(
+            &ConfigKey::Config,
+            &Config {
+                admin,
+                asset,
+                name,
+                symbol,
+                decimals,
+                decimals_offset,
+                entry_fee_bps: 0,
+                exit_fee_bps: 0,
+            }
+        );
         env.storage().instance().set(&DataKey::TotalSupply, &0i128);
+        env.storage().instance().set(&DataKey::LastFeeAccrual, &env.ledger().sequence());
+        env.storage().instance().set(&AccountingKey::LastKnownAssets, &0i128);
+        env.storage().instance().set(&AccountingKey::LastRate, &Self::one_asset(&env)?);
+        env.storage().instance().set(&UpgradeKey::Version, &CONTRACT_VERSION);
+        if let Some(mode) = rounding_mode {
+            env.storage().instance().set(&DataKey::RoundingMode, &mode);
+        }
         
         Ok(())
     }
 
-    pub fn name(env: Env) -> String {
-        env.storage().instance().get(&DataKey::Name).unwrap_or(String::from_str(&env, "Vault"))
+    // Initializes and makes the first deposit from `seeder` in the same call, with dead shares
+    // enabled, so the vault never sits at zero supply where a donation can inflate the share
+    // price. Returns the shares minted to `seeder`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_with_deposit(
+        env: Env,
+        admin: Address,
+        asset: Address,
+        name: String,
+        symbol: String,
+        decimals: u32,
+        seeder: Address,
+        seed_assets: i128,
+    ) -> Result<i128, Error> {
+        Self::initialize(env.clone(), admin, asset, name, symbol, decimals, 0, None)?;
+        env.storage().instance().set(&DataKey::DeadShares, &true);
+        Self::deposit(env, seeder.clone(), seed_assets, seeder)
     }
 
-    pub fn symbol(env: Env) -> String {
-        env.storage().instance().get(&DataKey::Symbol).unwrap_or(String::from_str(&env, "VAULT"))
+    // For factories: names the shares after the asset, "v" + its name and "v" + its symbol.
+    // Assets without readable metadata get "Vault Shares" / "vSHARE".
+    pub fn initialize_auto(env: Env, admin: Address, asset: Address, decimals: u32) -> Result<(), Error> {
+        let asset_client = token::Client::new(&env, &asset);
+        let name = match asset_client.try_name() {
+            Ok(Ok(name)) => Self::prefixed(&env, &name),
+            _ => None,
+        };
+        let symbol = match asset_client.try_symbol() {
+            Ok(Ok(symbol)) => Self::prefixed(&env, &symbol),
+            _ => None,
+        };
+        
+        Self::initialize(
+            env.clone(),
+            admin,
+            asset,
+            name.unwrap_or(String::from_str(&env, "Vault Shares")),
+            symbol.unwrap_or(String::from_str(&env, "vSHARE")),
+            decimals,
+            0,
+            None,
+        )
     }
 
-    pub fn decimals(env: Env) -> u32 {
-        env.storage().instance().get(&DataKey::Decimals).unwrap_or(18)
+    pub fn is_initialized(env: Env) -> bool {
+        Self::load_config(&env).is_some()
     }
 
-    pub fn total_supply(env: Env) -> i128 {
-        env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
+    pub fn config(env: Env) -> Result<Config, Error> {
+        Self::load_config(&env).ok_or(Error::NotInitialized)
     }
 
-    pub fn balance_of(env: Env, account: Address) -> i128 {
-        env.storage().instance().get(&DataKey::Balance(account)).unwrap_or(0)
+    // `None` means the `convert_to_*` views round down
+    pub fn rounding_mode(env: Env) -> Option<RoundingMode> {
+        env.storage().instance().get(&DataKey::RoundingMode)
     }
 
-    pub fn allowance(env: Env, owner: Address, spender: Address) -> i128 {
-        env.storage().instance().get(&DataKey::Allowance(owner, spender)).unwrap_or(0)
+    pub fn admin(env: Env) -> Result<Address, Error> {
+        Self::config(env).map(|config| config.admin)
     }
 
-    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) -> Result<bool, Error> {
-        from.require_auth();
-        Self::transfer_internal(&env, from, to, amount)?;
-        Ok(true)
+    // Kept for existing callers; only proposes `new_admin`, who still has to `accept_admin`
+    pub fn set_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        Self::propose_admin(env, new_admin)
     }
 
-    pub fn approve(env: Env, from: Address, spender: Address, amount: i128) -> bool {
-        from.require_auth();
-        env.storage().instance().set(&DataKey::Allowance(from.clone(), spender.clone()), &amount);
+    pub fn pending_admin(env: Env) -> Option<Address> {
+        env.storage().instance().get(&AccessKey::PendingAdmin)
+    }
+
+    // First step of a two-step transfer; the admin doesn't change until `new_admin` accepts.
+    // Proposing again replaces the pending admin.
+    pub fn propose_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        env.storage().instance().set(&AccessKey::PendingAdmin, &new_admin);
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "admin_proposed"), admin), new_admin);
+        Ok(())
+    }
+
+    pub fn accept_admin(env: Env) -> Result<(), Error> {
+        let new_admin = Self::pending_admin(env.clone()).ok_or(Error::NoPendingAdmin)?;
+        new_admin.require_auth();
+        let admin = Self::admin(env.clone())?;
+        Self::update_config(&env, |config| config.admin = new_admin.clone())?;
+        env.storage().instance().remove(&AccessKey::PendingAdmin);
         
         env.events().publish(
-            (symbol_short!("approve"), from, spender),
-            amount
+            (EVENT_VERSION, Symbol::new(&env, "admin_changed"), admin),
+            new_admin
         );
-        true
+        Ok(())
     }
 
-    pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) -> Result<bool, Error> {
-        spender.require_auth();
+    pub fn cancel_admin_transfer(env: Env) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        let pending = Self::pending_admin(env.clone()).ok_or(Error::NoPendingAdmin)?;
+        env.storage().instance().remove(&AccessKey::PendingAdmin);
         
-        let allowance = Self::allowance(env.clone(), from.clone(), spender.clone());
-        if allowance < amount {
-            return Err(Error::InsufficientAllowance);
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "admin_transfer_cancelled"), admin), pending);
+        Ok(())
+    }
+
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("upgraded"), admin), new_wasm_hash);
+        Ok(())
+    }
+
+    // Storage layout version; vaults initialized before versioning read as 0
+    pub fn version(env: Env) -> u32 {
+        env.storage().instance().get(&UpgradeKey::Version).unwrap_or(0)
+    }
+
+    // Run after `upgrade` to apply every migration between the stored version and
+    // `target_version` in order. Each step runs once; asking for a version already reached fails.
+    pub fn migrate(env: Env, target_version: u32) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        let from = Self::version(env.clone());
+        if target_version <= from {
+            return Err(Error::AlreadyMigrated);
+        }
+        if target_version > CONTRACT_VERSION {
+            return Err(Error::InvalidVersion);
         }
         
-        if allowance != i128::MAX {
-            env.storage().instance().set(
-                &DataKey::Allowance(from.clone(), spender),
-                &(allowance - amount)
-            );
+        for version in (from + 1)..=target_version {
+            match version {
+                // Seed the asset watermark and supply history, which older vaults never wrote.
+                // Per-account history starts at each account's next balance change.
+                1 => {
+                    if !env.storage().instance().has(&AccountingKey::LastKnownAssets) {
+                        Self::set_known_assets(&env, Self::total_assets(env.clone())?);
+                    }
+                    if !env.storage().persistent().has(&AccountingKey::SupplyCheckpointCount) {
+                        Self::set_total_supply(&env, Self::total_supply(env.clone()));
+                    }
+                }
+                // Pack the settings kept under separate keys into `Config`. A setter called
+                // since the upgrade has already written a newer `Config`, which is kept.
+                2 => {
+                    if let Some(config) = Self::legacy_config(&env) {
+                        if !env.storage().instance().has(&ConfigKey::Config) {
+                            env.storage().instance().set(&ConfigKey::Config, &config);
+                        }
+                        for key in [
+                            DataKey::Admin,
+                            DataKey::Asset,
+                            DataKey::Name,
+                            DataKey::Symbol,
+                            DataKey::Decimals,
+                            DataKey::DecimalsOffset,
+                            DataKey::EntryFeeBps,
+                            DataKey::ExitFeeBps,
+                        ] {
+                            env.storage().instance().remove(&key);
+                        }
+                    }
+                }
+                _ => return Err(Error::InvalidVersion),
+            }
         }
+        env.storage().instance().set(&UpgradeKey::Version, &target_version);
         
-        Self::transfer_internal(&env, from, to, amount)?;
-        Ok(true)
+        env.events().publish((EVENT_VERSION, symbol_short!("migrated"), admin), (from, target_version));
+        Ok(())
     }
 
-    // ERC4626 Vault Interface
-    pub fn asset(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Asset).unwrap()
+    pub fn paused(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::Paused).unwrap_or(false)
     }
 
-    pub fn total_assets(env: Env) -> i128 {
-        let asset_address = Self::asset(env.clone());
-        let asset_client = token::Client::new(&env, &asset_address);
-        asset_client.balance(&env.current_contract_address())
+    pub fn pause(env: Env, caller: Address) -> Result<(), Error> {
+        Self::require_role(&env, PAUSER, &caller)?;
+        env.storage().instance().set(&DataKey::Paused, &true);
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("pause"), caller), ());
+        Ok(())
     }
 
-    pub fn convert_to_shares(env: Env, assets: i128) -> i128 {
-        Self::convert_to_shares_internal(&env, assets, false)
+    pub fn unpause(env: Env, caller: Address) -> Result<(), Error> {
+        Self::require_role(&env, PAUSER, &caller)?;
+        env.storage().instance().set(&DataKey::Paused, &false);
+        env.storage().instance().remove(&GuardKey::Tripped);
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("unpause"), caller), ());
+        Ok(())
     }
 
-    pub fn convert_to_assets(env: Env, shares: i128) -> i128 {
-        Self::convert_to_assets_internal(&env, shares, false)
+    pub fn is_shutdown(env: Env) -> bool {
+        env.storage().instance().get(&GuardKey::Shutdown).unwrap_or(false)
     }
 
-    pub fn max_deposit(_env: Env, _receiver: Address) -> i128 {
-        i128::MAX
+    // Irreversible. Deposits, mints, withdraws and flash loans stop for good; holders exit
+    // through `redeem` at their pro-rata share with exit and management fees waived.
+    pub fn shutdown(env: Env) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        env.storage().instance().set(&GuardKey::Shutdown, &true);
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("shutdown"), admin), ());
+        Ok(())
     }
 
-    pub fn max_mint(_env: Env, _receiver: Address) -> i128 {
-        i128::MAX
+    // Circuit breaker: once withdrawals within one ledger exceed this share of total assets, in
+    // basis points, the vault pauses itself and blocks exits too until unpaused. Zero disables it.
+    pub fn max_outflow_per_ledger(env: Env) -> u32 {
+        env.storage().instance().get(&GuardKey::MaxOutflowPerLedger).unwrap_or(0)
     }
 
-    pub fn max_withdraw(env: Env, owner: Address) -> i128 {
-        let shares = Self::balance_of(env.clone(), owner);
-        Self::convert_to_assets_internal(&env, shares, false)
+    pub fn set_max_outflow_per_ledger(env: Env, max_bps: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&GuardKey::MaxOutflowPerLedger, &max_bps);
+        Ok(())
     }
 
-    pub fn max_redeem(env: Env, owner: Address) -> i128 {
-        Self::balance_of(env, owner)
+    pub fn breaker_tripped(env: Env) -> bool {
+        env.storage().instance().get(&GuardKey::Tripped).unwrap_or(false)
     }
 
-    pub fn preview_deposit(env: Env, assets: i128) -> i128 {
-        Self::convert_to_shares_internal(&env, assets, false)
+    // Price smoothing: conversions price shares off an average of the assets backing each
+    // share instead of the live balance. The first deposit, mint, withdraw or redeem in a
+    // ledger moves the average this share of the way, in basis points, towards the live value,
+    // so a donation only shifts prices gradually. Real gains and losses are phased in the same
+    // way, and anyone entering or leaving meanwhile trades at the lagging price. Zero disables it.
+    pub fn price_smoothing(env: Env) -> u32 {
+        env.storage().instance().get(&GuardKey::PriceSmoothingBps).unwrap_or(0)
     }
 
-    pub fn preview_mint(env: Env, shares: i128) -> i128 {
-        Self::convert_to_assets_internal(&env, shares, true)
+    pub fn set_price_smoothing(env: Env, weight_bps: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        if weight_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        env.storage().instance().set(&GuardKey::PriceSmoothingBps, &weight_bps);
+        // Restart from the live price rather than one recorded under an older setting
+        env.storage().instance().remove(&AccountingKey::SmoothedAssets);
+        Ok(())
     }
 
-    pub fn preview_withdraw(env: Env, assets: i128) -> i128 {
-        Self::convert_to_shares_internal(&env, assets, true)
+    // The total assets conversions are priced against; `total_assets` when smoothing is off
+    pub fn smoothed_assets(env: Env) -> Result<i128, Error> {
+        let total = Self::total_assets(env.clone())?;
+        Self::pricing_assets(&env, total, Self::total_supply(env.clone()))
     }
 
-    pub fn preview_redeem(env: Env, shares: i128) -> i128 {
-        Self::convert_to_assets_internal(&env, shares, false)
+    // Roles
+    pub fn has_role(env: Env, role: Symbol, account: Address) -> bool {
+        env.storage().instance().has(&AccessKey::Role(role, account))
     }
 
-    pub fn deposit(env: Env, assets: i128, receiver: Address) -> Result<i128, Error> {
-        let caller = env.current_contract_address();
-        caller.require_auth();
-        
-        if assets <= 0 {
-            return Err(Error::ZeroAssets);
-        }
-        
-        let shares = Self::preview_deposit(env.clone(), assets);
-        if shares <= 0 {
-            return Err(Error::ZeroShares);
-        }
-        
-        let asset_address = Self::asset(env.clone());
-        let asset_client = token::Client::new(&env, &asset_address);
-        asset_client.transfer(&caller, &env.current_contract_address(), &assets);
-        
-        Self::mint_internal(&env, receiver.clone(), shares);
-        
-        env.events().publish(
-            (symbol_short!("deposit"), caller, receiver),
-            (assets, shares)
-        );
+    pub fn grant_role(env: Env, role: Symbol, account: Address) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        env.storage().instance().set(&AccessKey::Role(role.clone(), account.clone()), &());
         
-        Ok(shares)
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "role_granted"), role, account), admin);
+        Ok(())
     }
 
-    pub fn mint(env: Env, shares: i128, receiver: Address) -> Result<i128, Error> {
-        let caller = env.current_contract_address();
-        caller.require_auth();
-        
-        if shares <= 0 {
-            return Err(Error::ZeroShares);
-        }
-        
-        let assets = Self::preview_mint(env.clone(), shares);
-        if assets <= 0 {
-            return Err(Error::ZeroAssets);
-        }
-        
-        let asset_address = Self::asset(env.clone());
-        let asset_client = token::Client::new(&env, &asset_address);
-        asset_client.transfer(&caller, &env.current_contract_address(), &assets);
-        
-        Self::mint_internal(&env, receiver.clone(), shares);
-        
-        env.events().publish(
-            (symbol_short!("deposit"), caller, receiver),
-            (assets, shares)
-        );
+    pub fn revoke_role(env: Env, role: Symbol, account: Address) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        env.storage().instance().remove(&AccessKey::Role(role.clone(), account.clone()));
         
-        Ok(assets)
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "role_revoked"), role, account), admin);
+        Ok(())
     }
 
-    pub fn withdraw(env: Env, assets: i128, receiver: Address, owner: Address) -> Result<i128, Error> {
-        let caller = env.current_contract_address();
-        caller.require_auth();
-        
-        if assets <= 0 {
-            return Err(Error::ZeroAssets);
-        }
-        
-        let shares = Self::preview_withdraw(env.clone(), assets);
-        if shares <= 0 {
-            return Err(Error::ZeroShares);
-        }
-        
-        if caller != owner {
-            let allowance = Self::allowance(env.clone(), owner.clone(), caller.clone());
-            if allowance < shares {
-                return Err(Error::InsufficientAllowance);
-            }
-            if allowance != i128::MAX {
-                env.storage().instance().set(
-                    &DataKey::Allowance(owner.clone(), caller.clone()),
-                    &(allowance - shares)
-                );
-            }
-        }
-        
-        Self::burn_internal(&env, owner.clone(), shares)?;
-        
-        let asset_address = Self::asset(env.clone());
-        let asset_client = token::Client::new(&env, &asset_address);
-        asset_client.transfer(&env.current_contract_address(), &receiver, &assets);
-        
-        env.events().publish(
-            (symbol_short!("withdraw"), caller, receiver, owner),
-            (assets, shares)
-        );
-        
-        Ok(shares)
+    // Allowlist
+    // While enabled, only allowlisted addresses can receive shares from a deposit or mint and
+    // withdraw or redeem as owner. Share transfers are not gated.
+    pub fn allowlist_enabled(env: Env) -> bool {
+        env.storage().instance().get(&AccessKey::AllowlistEnabled).unwrap_or(false)
     }
 
-    pub fn redeem(env: Env, shares: i128, receiver: Address, owner: Address) -> Result<i128, Error> {
-        let caller = env.current_contract_address();
-        caller.require_auth();
-        
-        if shares <= 0 {
-            return Err(Error::ZeroShares);
-        }
-        
-        let assets = Self::preview_redeem(env.clone(), shares);
-        if assets <= 0 {
-            return Err(Error::ZeroAssets);
-        }
-        
-        if caller != owner {
-            let allowance = Self::allowance(env.clone(), owner.clone(), caller.clone());
-            if allowance < shares {
-                return Err(Error::InsufficientAllowance);
-            }
-            if allowance != i128::MAX {
-                env.storage().instance().set(
-                    &DataKey::Allowance(owner.clone(), caller.clone()),
-                    &(allowance - shares)
-                );
-            }
-        }
-        
-        Self::burn_internal(&env, owner.clone(), shares)?;
-        
-        let asset_address = Self::asset(env.clone());
-        let asset_client = token::Client::new(&env, &asset_address);
-        asset_client.transfer(&env.current_contract_address(), &receiver, &assets);
-        
-        env.events().publish(
-            (symbol_short!("withdraw"), caller, receiver, owner),
-            (assets, shares)
-        );
-        
-        Ok(assets)
+    pub fn set_allowlist_enabled(env: Env, enabled: bool) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&AccessKey::AllowlistEnabled, &enabled);
+        Ok(())
     }
 
-    fn transfer_internal(env: &Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
-        let from_balance = Self::balance_of(env.clone(), from.clone());
-        if from_balance < amount {
-            return Err(Error::InsufficientBalance);
+    pub fn is_allowlisted(env: Env, who: Address) -> bool {
+        env.storage().instance().has(&AccessKey::Allowlisted(who))
+    }
+
+    pub fn set_allowlist(env: Env, who: Address, allowed: bool) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        if allowed {
+            env.storage().instance().set(&AccessKey::Allowlisted(who.clone()), &());
+        } else {
+            env.storage().instance().remove(&AccessKey::Allowlisted(who.clone()));
         }
         
-        env.storage().instance().set(&DataKey::Balance(from.clone()), &(from_balance - amount));
-        
-        let to_balance = Self::balance_of(env.clone(), to.clone());
-        env.storage().instance().set(&DataKey::Balance(to.clone()), &(to_balance + amount));
-        
-        env.events().publish(
-            (symbol_short!("transfer"), from, to),
-            amount
-        );
-        
+        env.events().publish((EVENT_VERSION, symbol_short!("allowlist"), who), (allowed, admin));
         Ok(())
     }
 
-    fn mint_internal(env: &Env, account: Address, amount: i128) {
-        let balance = Self::balance_of(env.clone(), account.clone());
-        env.storage().instance().set(&DataKey::Balance(account.clone()), &(balance + amount));
-        
-        let total_supply = Self::total_supply(env.clone());
-        env.storage().instance().set(&DataKey::TotalSupply, &(total_supply + amount));
-        
-        env.events().publish(
-            (symbol_short!("mint"), account),
-            amount
-        );
+    // Blocklist
+    // A blocked address can't send or receive shares, receive shares from a deposit or mint,
+    // or withdraw or redeem as owner until it is unblocked. Applies whether or not the
+    // allowlist is enabled.
+    pub fn is_blocked(env: Env, who: Address) -> bool {
+        env.storage().instance().has(&AccessKey::Blocked(who))
     }
 
-    fn burn_internal(env: &Env, account: Address, amount: i128) -> Result<(), Error> {
-        let balance = Self::balance_of(env.clone(), account.clone());
-        if balance < amount {
-            return Err(Error::InsufficientBalance);
+    pub fn set_blocked(env: Env, who: Address, blocked: bool) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        if blocked {
+            env.storage().instance().set(&AccessKey::Blocked(who.clone()), &());
+        } else {
+            env.storage().instance().remove(&AccessKey::Blocked(who.clone()));
         }
         
-        env.storage().instance().set(&DataKey::Balance(account.clone()), &(balance - amount));
-        
-        let total_supply = Self::total_supply(env.clone());
-        env.storage().instance().set(&DataKey::TotalSupply, &(total_supply - amount));
-        
-        env.events().publish(
-            (symbol_short!("burn"), account),
-            amount
-        );
-        
+        env.events().publish((EVENT_VERSION, symbol_short!("blocked"), who), (blocked, admin));
         Ok(())
     }
 
-    fn convert_to_shares_internal(env: &Env, assets: i128, round_up: bool) -> i128 {
-        let supply = Self::total_supply(env.clone());
-        let total = Self::total_assets(env.clone());
-        
-        if supply == 0 || total == 0 {
-            return assets;
-        }
-        
-        let result = (assets * supply) / total;
-        if round_up && (assets * supply) % total > 0 {
-            result + 1
-        } else {
-            result
-        }
+    pub fn name(env: Env) -> String {
+        Self::load_config(&env).map(|config| config.name).unwrap_or(String::from_str(&env, "Vault"))
     }
 
-    fn convert_to_assets_internal(env: &Env, shares: i128, round_up: bool) -> i128 {
-        let supply = Self::total_supply(env.clone());
-        let total = Self::total_assets(env.clone());
-        
-        if supply == 0 || total == 0 {
-            return shares;
-        }
+    pub fn symbol(env: Env) -> String {
+        Self::load_config(&env).map(|config| config.symbol).unwrap_or(String::from_str(&env, "VAULT"))
+    }
+
+    // Decimals stay fixed; changing them would rescale every balance
+    pub fn set_metadata(env: Env, name: String, symbol: String) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        Self::update_config(&env, |config| {
+            config.name = name.clone();
+            config.symbol = symbol.clone();
+        })?;
         
-        let result = (shares * total) / supply;
-        if round_up && (shares * total) % supply > 0 {
-            result + 1
-        } else {
-            result
-        }
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "metadata_updated"), admin), (name, symbol));
+        Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn decimals(env: Env) -> u32 {
+        Self::asset_decimals(env.clone()) + Self::decimals_offset(env)
+    }
+
+    pub fn asset_decimals(env: Env) -> u32 {
+        Self::load_config(&env).map_or(18, |config| config.decimals)
+    }
+
+    pub fn decimals_offset(env: Env) -> u32 {
+        Self::load_config(&env).map_or(0, |config| config.decimals_offset)
+    }
+
+    // Splits a share amount into whole and fractional parts by `decimals`. Both parts carry
+    // the sign of `amount`, so `whole * 10^decimals + fractional == amount`.
+    pub fn split_amount(env: Env, amount: i128) -> Result<(i128, i128), Error> {
+        let one = Self::one_share(&env)?;
+        Ok((amount / one, amount % one))
+    }
+
+    pub fn total_supply(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
+    }
+
+    // SEP-41 name for `balance_of`
+    pub fn balance(env: Env, id: Address) -> i128 {
+        Self::balance_of(env, id)
+    }
+
+    // A blocked account's shares are frozen in place, so none of its balance is spendable
+    pub fn spendable_balance(env: Env, id: Address) -> i128 {
+        if Self::is_blocked(env.clone(), id.clone()) {
+            return 0;
+        }
+        Self::balance_of(env, id)
+    }
+
+    pub fn authorized(env: Env, id: Address) -> bool {
+        !Self::is_blocked(env, id)
+    }
+
+    // Balances live in instance storage and share the contract instance's TTL. Contracts can't
+    // read TTLs through this SDK, so check the instance entry's live-until ledger over RPC.
+    pub fn balance_of(env: Env, account: Address) -> i128 {
+        env.storage().instance().get(&DataKey::Balance(account)).unwrap_or(0)
+    }
+
+    // Balances in the order of `accounts`, at most `MAX_BALANCE_QUERY` of them
+    pub fn balances_of(env: Env, accounts: Vec<Address>) -> Result<Vec<i128>, Error> {
+        if accounts.len() > MAX_BALANCE_QUERY {
+            return Err(Error::BatchTooLarge);
+        }
+        let mut balances = Vec::new(&env);
+        for account in accounts.iter() {
+            balances.push_back(Self::balance_of(env.clone(), account));
+        }
+        Ok(balances)
+    }
+
+    // `account`'s balance at the end of `ledger`, for governance snapshots
+    pub fn balance_of_at(env: Env, account: Address, ledger: u32) -> i128 {
+        Self::snapshot_at(
+            &env,
+            AccountingKey::BalanceCheckpointCount(account.clone()),
+            |index| AccountingKey::BalanceCheckpoint(account.clone(), index),
+            ledger,
+        )
+    }
+
+    pub fn total_supply_at(env: Env, ledger: u32) -> i128 {
+        Self::snapshot_at(&env, AccountingKey::SupplyCheckpointCount, AccountingKey::SupplyCheckpoint, ledger)
+    }
+
+    pub fn allowance(env: Env, owner: Address, spender: Address) -> i128 {
+        Self::allowance_value(&env, owner, spender).amount
+    }
+
+    // An allowance of `i128::MAX` is never decremented by `transfer_from`, `burn_from`,
+    // `withdraw`, `redeem` or `request_withdraw`, though it still lapses at its expiration ledger
+    pub fn is_infinite_allowance(env: Env, owner: Address, spender: Address) -> bool {
+        Self::allowance_value(&env, owner, spender).amount == i128::MAX
+    }
+
+    // SEP-41 signature, so `soroban_sdk::token::Client` works against the vault
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+        from.require_auth();
+        Self::transfer_internal(&env, from, to, amount)
+    }
+
+    // Transfers only if `from` keeps at least `min_remaining` shares afterwards
+    pub fn transfer_checked(
+        env: Env,
+        from: Address,
+        to: Address,
+        amount: i128,
+        min_remaining: i128,
+    ) -> Result<bool, Error> {
+        from.require_auth();
+        Self::require_non_negative(amount)?;
+        
+        let remaining = Self::balance_of(env.clone(), from.clone()) - amount;
+        if remaining < min_remaining {
+            return Err(Error::WouldViolateMinimum);
+        }
+        
+        Self::transfer_internal(&env, from, to, amount)?;
+        Ok(true)
+    }
+
+    // `u32::MAX` as `expiration_ledger` never expires
+    pub fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) -> Result<(), Error> {
+        from.require_auth();
+        Self::require_non_negative(amount)?;
+        Self::approve_internal(&env, from, spender, amount, expiration_ledger);
+        Ok(())
+    }
+
+    pub fn approve_with_expiry(
+        env: Env,
+        from: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+    ) -> Result<bool, Error> {
+        from.require_auth();
+        Self::require_non_negative(amount)?;
+        Self::approve_internal(&env, from, spender, amount, expiration_ledger);
+        Ok(true)
+    }
+
+    pub fn increase_allowance(env: Env, from: Address, spender: Address, added: i128) -> Result<bool, Error> {
+        from.require_auth();
+        Self::require_non_negative(added)?;
+        
+        let allowance = Self::allowance_value(&env, from.clone(), spender.clone());
+        let new_allowance = allowance.amount.checked_add(added).ok_or(Error::Overflow)?;
+        
+        Self::approve_internal(&env, from, spender, new_allowance, allowance.expiration_ledger);
+        Ok(true)
+    }
+
+    pub fn decrease_allowance(env: Env, from: Address, spender: Address, subtracted: i128) -> Result<bool, Error> {
+        from.require_auth();
+        Self::require_non_negative(subtracted)?;
+        
+        let allowance = Self::allowance_value(&env, from.clone(), spender.clone());
+        if allowance.amount < subtracted {
+            return Err(Error::InsufficientAllowance);
+        }
+        
+        Self::approve_internal(&env, from, spender, allowance.amount - subtracted, allowance.expiration_ledger);
+        Ok(true)
+    }
+
+    // Sets an allowance from an Ed25519 signature by `owner`, so anyone can submit it on their
+    // behalf. The signed message is the XDR of (vault, owner, spender, amount,
+    // expiration_ledger, nonce); only account (G...) owners can sign, and a bad signature traps.
+    pub fn permit(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+        nonce: u64,
+        signature: BytesN<64>,
+    ) -> Result<(), Error> {
+        Self::require_non_negative(amount)?;
+        if expiration_ledger < env.ledger().sequence() {
+            return Err(Error::PermitExpired);
+        }
+        
+        if nonce != Self::nonces(env.clone(), owner.clone()) {
+            return Err(Error::InvalidNonce);
+        }
+        
+        let public_key = Self::account_public_key(&env, &owner)?;
+        let message = (
+            env.current_contract_address(),
+            owner.clone(),
+            spender.clone(),
+            amount,
+            expiration_ledger,
+            nonce,
+        )
+            .to_xdr(&env);
+        env.crypto().ed25519_verify(&public_key, &message, &signature);
+        
+        Self::use_nonce(&env, &owner);
+        Self::approve_internal(&env, owner, spender, amount, expiration_ledger);
+        Ok(())
+    }
+
+    // Next nonce a signature from `owner` must use
+    pub fn nonces(env: Env, owner: Address) -> u64 {
+        env.storage().persistent().get(&DataKey::Nonce(owner)).unwrap_or(0)
+    }
+
+    // Accounts with a nonzero balance, in no particular order. Removing a holder moves the
+    // last one into its slot, so pages can shift between reads taken at different ledgers.
+    pub fn holders(env: Env, start: u32, limit: u32) -> Vec<Address> {
+        let end = start.saturating_add(limit).min(Self::holder_count(&env));
+        let mut holders = Vec::new(&env);
+        for index in start..end {
+            holders.push_back(env.storage().instance().get(&DataKey::HolderIndex(index)).unwrap());
+        }
+        holders
+    }
+
+    // Number of accounts with a nonzero balance. Self-transfers are rejected, so a transfer
+    // always moves exactly two distinct balances.
+    pub fn total_holders(env: Env) -> u32 {
+        Self::holder_count(&env)
+    }
+
+    // Sequence number of the latest transfer, mint or burn event. Those events carry
+    // `(amount, seq)` as data, numbered contiguously from 1 across all accounts.
+    pub fn current_event_seq(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::EventSeq).unwrap_or(0)
+    }
+
+    pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+        spender.require_auth();
+        
+        Self::spend_allowance(&env, from.clone(), spender, amount)?;
+        
+        Self::transfer_internal(&env, from, to, amount)
+    }
+
+    pub fn burn(env: Env, from: Address, amount: i128) -> Result<(), Error> {
+        from.require_auth();
+        Self::burn_internal(&env, from, amount)?;
+        Self::publish_rate(&env, Self::total_assets(env.clone())?);
+        Ok(())
+    }
+
+    pub fn burn_from(env: Env, spender: Address, from: Address, amount: i128) -> Result<(), Error> {
+        spender.require_auth();
+        
+        Self::spend_allowance(&env, from.clone(), spender, amount)?;
+        
+        Self::burn_internal(&env, from, amount)?;
+        Self::publish_rate(&env, Self::total_assets(env.clone())?);
+        Ok(())
+    }
+
+    // ERC4626 Vault Interface
+    pub fn asset(env: Env) -> Result<Address, Error> {
+        Self::config(env).map(|config| config.asset)
+    }
+
+    // Idle balance plus whatever has been deployed out of the vault and the value of any
+    // basket assets, less what queued withdrawals are owed
+    pub fn total_assets(env: Env) -> Result<i128, Error> {
+        Self::total_from_idle(&env, Self::idle_assets(env.clone())?)
+    }
+
+    // Assets held by the vault itself and available for immediate withdrawal
+    pub fn idle_assets(env: Env) -> Result<i128, Error> {
+        let asset_address = Self::asset(env.clone())?;
+        let asset_client = token::Client::new(&env, &asset_address);
+        Ok(asset_client.balance(&env.current_contract_address()))
+    }
+
+    pub fn deployed_assets(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::DeployedAssets).unwrap_or(0)
+    }
+
+    pub fn convert_to_shares(env: Env, assets: i128) -> Result<i128, Error> {
+        let supply = Self::total_supply(env.clone());
+        let total = Self::pricing_assets(&env, Self::total_assets(env.clone())?, supply)?;
+        Self::convert_to_shares_with_mode(&env, assets, total, supply, Self::view_rounding(&env))
+    }
+
+    pub fn convert_to_assets(env: Env, shares: i128) -> Result<i128, Error> {
+        let supply = Self::total_supply(env.clone());
+        let total = Self::pricing_assets(&env, Self::total_assets(env.clone())?, supply)?;
+        Self::convert_to_assets_with_mode(&env, shares, total, supply, Self::view_rounding(&env))
+    }
+
+    // `convert_to_shares` plus the assets the rounding leaves with the vault: `assets` minus the
+    // exact value of the returned shares, truncated towards zero. Negative when the configured
+    // rounding mode rounds the shares up.
+    pub fn convert_to_shares_detailed(env: Env, assets: i128) -> Result<(i128, i128), Error> {
+        let supply = Self::total_supply(env.clone());
+        let total = Self::pricing_assets(&env, Self::total_assets(env.clone())?, supply)?;
+        let shares = Self::convert_to_shares_with_mode(&env, assets, total, supply, Self::view_rounding(&env))?;
+        
+        let scaled_supply = supply.checked_add(Self::virtual_shares(&env)?).ok_or(Error::Overflow)?;
+        let scaled_total = total.checked_add(1).ok_or(Error::Overflow)?;
+        let exact = assets.checked_mul(scaled_supply).ok_or(Error::Overflow)?;
+        let issued = shares.checked_mul(scaled_total).ok_or(Error::Overflow)?;
+        Ok((shares, (exact - issued) / scaled_supply))
+    }
+
+    // Same math as `convert_to_shares` against caller-supplied vault figures, for simulating
+    // conversions in hypothetical states. Reads nothing but the decimals offset and rounding mode.
+    pub fn convert_to_shares_at(
+        env: Env,
+        assets: i128,
+        total_assets_override: i128,
+        total_supply_override: i128,
+    ) -> Result<i128, Error> {
+        Self::check_overrides(total_assets_override, total_supply_override)?;
+        let mode = Self::view_rounding(&env);
+        Self::convert_to_shares_with_mode(&env, assets, total_assets_override, total_supply_override, mode)
+    }
+
+    pub fn convert_to_assets_at(
+        env: Env,
+        shares: i128,
+        total_assets_override: i128,
+        total_supply_override: i128,
+    ) -> Result<i128, Error> {
+        Self::check_overrides(total_assets_override, total_supply_override)?;
+        let mode = Self::view_rounding(&env);
+        Self::convert_to_assets_with_mode(&env, shares, total_assets_override, total_supply_override, mode)
+    }
+
+    pub fn vault_info(env: Env) -> Result<VaultInfo, Error> {
+        let total = Self::total_assets(env.clone())?;
+        Ok(VaultInfo {
+            name: Self::name(env.clone()),
+            symbol: Self::symbol(env.clone()),
+            decimals: Self::decimals(env.clone()),
+            asset: Self::asset(env.clone())?,
+            total_supply: Self::total_supply(env.clone()),
+            total_assets: total,
+            price_per_share: Self::price_per_share_internal(&env, total)?,
+        })
+    }
+
+    pub fn status(env: Env) -> Result<VaultStatus, Error> {
+        let idle = Self::idle_assets(env.clone())?;
+        let total = Self::total_from_idle(&env, idle)?;
+        Ok(VaultStatus {
+            paused: Self::paused(env.clone()),
+            shutdown: Self::is_shutdown(env.clone()),
+            solvent: Self::is_solvent_internal(&env, total)?,
+            deposit_room: Self::global_deposit_remaining(&env, total),
+            idle_assets: idle,
+        })
+    }
+
+    // Assets for one whole share; an empty vault reports one whole asset (1.0)
+    pub fn price_per_share(env: Env) -> Result<i128, Error> {
+        Self::price_per_share_internal(&env, Self::total_assets(env.clone())?)
+    }
+
+    // Shares for one whole asset; an empty vault reports one whole share (1.0)
+    pub fn shares_per_asset(env: Env) -> Result<i128, Error> {
+        if Self::total_supply(env.clone()) == 0 {
+            return Self::one_share(&env);
+        }
+        Self::convert_to_shares_internal(&env, Self::one_asset(&env)?, Self::total_assets(env.clone())?, false)
+    }
+
+    // Raw (total_assets, total_supply) so integrators can apply their own rounding; an empty
+    // vault reports (1, 1). The virtual share offset is not included.
+    pub fn exchange_rate(env: Env) -> Result<(i128, i128), Error> {
+        let supply = Self::total_supply(env.clone());
+        if supply == 0 {
+            return Ok((1, 1));
+        }
+        Ok((Self::total_assets(env)?, supply))
+    }
+
+    pub fn max_deposit(env: Env, receiver: Address) -> Result<i128, Error> {
+        if Self::is_shutdown(env.clone()) || Self::check_access(&env, &receiver).is_err() {
+            return Ok(0);
+        }
+        if !env.storage().instance().has(&DataKey::DepositCap) {
+            return Ok(Self::user_deposit_remaining(&env, &receiver));
+        }
+        Ok(Self::max_deposit_internal(&env, &receiver, Self::total_assets(env.clone())?))
+    }
+
+    pub fn max_mint(env: Env, receiver: Address) -> Result<i128, Error> {
+        if Self::is_shutdown(env.clone()) || Self::check_access(&env, &receiver).is_err() {
+            return Ok(0);
+        }
+        if !env.storage().instance().has(&DataKey::DepositCap)
+            && !env.storage().instance().has(&DataKey::UserDepositCap)
+        {
+            return Ok(i128::MAX);
+        }
+        let total = Self::total_assets(env.clone())?;
+        Self::preview_deposit_internal(&env, Self::max_deposit_internal(&env, &receiver, total), total)
+    }
+
+    // Both maxima are what could be executed now: the owner's whole position, unless paying it
+    // out plus the exit fee would take more than the vault holds idle
+    pub fn max_withdraw(env: Env, owner: Address) -> Result<i128, Error> {
+        if Self::is_shutdown(env.clone()) || Self::check_access(&env, &owner).is_err() {
+            return Ok(0);
+        }
+        let shares = Self::balance_of(env.clone(), owner);
+        let total = Self::total_assets(env.clone())?;
+        let assets = Self::preview_redeem_internal(&env, shares, total)?;
+        Ok(assets.min(Self::idle_payable(&env, total)?))
+    }
+
+    pub fn max_redeem(env: Env, owner: Address) -> Result<i128, Error> {
+        if Self::check_access(&env, &owner).is_err() {
+            return Ok(0);
+        }
+        let shares = Self::balance_of(env.clone(), owner);
+        let total = Self::total_assets(env.clone())?;
+        // A redemption never owes more than the shares' value before the fee, so that value is
+        // what has to fit in the idle assets
+        let idle = Self::idle_available(&env, total)?.max(0);
+        if Self::convert_to_assets_internal(&env, shares, total, false)? <= idle {
+            return Ok(shares);
+        }
+        Ok(Self::convert_to_shares_internal(&env, idle, total, false)?.min(shares))
+    }
+
+    // Assumes the full `assets` arrives; `deposit` mints on the measured amount instead
+    pub fn preview_deposit(env: Env, assets: i128) -> Result<i128, Error> {
+        Self::preview_deposit_internal(&env, assets, Self::total_assets(env.clone())?)
+    }
+
+    // Breaks a deposit down into its fee, the assets added to the vault, the shares minted and
+    // the price per share afterwards. Like `preview_deposit` it assumes the full amount arrives
+    // and doesn't include management fees still to accrue.
+    pub fn simulate_deposit(env: Env, assets: i128) -> Result<DepositSimulation, Error> {
+        let total = Self::total_assets(env.clone())?;
+        let entry_fee = Self::fee_on_total(assets, Self::entry_fee_bps(env.clone()))?;
+        let net_assets = assets - entry_fee;
+        let shares = Self::preview_deposit_internal(&env, assets, total)?;
+        
+        let new_total = total.checked_add(net_assets).ok_or(Error::Overflow)?;
+        let new_supply = Self::total_supply(env.clone())
+            .checked_add(shares + Self::dead_shares_due(&env))
+            .ok_or(Error::Overflow)?;
+        let new_price_per_share = if new_supply == 0 {
+            Self::one_asset(&env)?
+        } else {
+            Self::convert_to_assets_with(&env, Self::one_share(&env)?, new_total, new_supply, false)?
+        };
+        
+        Ok(DepositSimulation {
+            shares,
+            entry_fee,
+            net_assets,
+            new_price_per_share,
+        })
+    }
+
+    pub fn preview_mint(env: Env, shares: i128) -> Result<i128, Error> {
+        Self::preview_mint_internal(&env, shares, Self::total_assets(env.clone())?)
+    }
+
+    pub fn preview_withdraw(env: Env, assets: i128) -> Result<i128, Error> {
+        Self::preview_withdraw_internal(&env, assets, Self::total_assets(env.clone())?)
+    }
+
+    pub fn preview_redeem(env: Env, shares: i128) -> Result<i128, Error> {
+        Self::preview_redeem_internal(&env, shares, Self::total_assets(env.clone())?)
+    }
+
+    pub fn deposit(env: Env, caller: Address, assets: i128, receiver: Address) -> Result<i128, Error> {
+        caller.require_auth();
+        Self::deposit_flow(env, caller, assets, receiver, false)
+    }
+
+    // Like `deposit`, but the vault pulls the assets from `payer` with `transfer_from`, so `payer`
+    // must have approved the vault on the asset first. Lets a sponsor fund shares for `receiver`.
+    pub fn deposit_from(env: Env, payer: Address, assets: i128, receiver: Address) -> Result<i128, Error> {
+        payer.require_auth();
+        Self::deposit_flow(env, payer, assets, receiver, true)
+    }
+
+    fn deposit_flow(env: Env, caller: Address, assets: i128, receiver: Address, use_allowance: bool) -> Result<i128, Error> {
+        Self::check_open(&env)?;
+        
+        Self::require_non_negative(assets)?;
+        if assets == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        Self::accrue_fees(&env, total)?;
+        Self::observe_price(&env, total)?;
+        Self::check_solvency(&env, total)?;
+        Self::check_deposit_limits(&env, &receiver, assets, total)?;
+        
+        if Self::preview_deposit_internal(&env, assets, total)? <= 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        // Shares are priced on what actually arrived, which is less than `assets` minus the
+        // entry fee for fee-on-transfer tokens
+        Self::non_reentrant(&env, || {
+            let received = Self::pull_assets(&env, &caller, assets, total, use_allowance)?;
+            
+            let shares = Self::convert_to_shares_internal(&env, received, total, false)? - Self::dead_shares_due(&env);
+            if shares <= 0 {
+                return Err(Error::ZeroShares);
+            }
+            
+            Self::record_deposit(&env, &receiver, assets)?;
+            Self::mint_deposit_shares(&env, &receiver, shares)?;
+            Self::publish_deposit(&env, caller.clone(), receiver.clone(), assets, shares);
+            Self::publish_rate(&env, total + received);
+            
+            Ok(shares)
+        })
+    }
+
+    pub fn mint(env: Env, caller: Address, shares: i128, receiver: Address) -> Result<i128, Error> {
+        caller.require_auth();
+        
+        Self::check_open(&env)?;
+        
+        Self::require_non_negative(shares)?;
+        if shares == 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        Self::accrue_fees(&env, total)?;
+        Self::observe_price(&env, total)?;
+        let assets = Self::preview_mint_internal(&env, shares, total)?;
+        if assets <= 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        // Caps apply to what the shares cost and are checked before anything moves, so a mint
+        // either goes through in full or not at all
+        Self::check_solvency(&env, total)?;
+        Self::check_deposit_limits(&env, &receiver, assets, total)?;
+        
+        Self::deposit_internal(&env, caller, receiver, assets, shares, total)?;
+        
+        Ok(assets)
+    }
+
+    // Mints shares to `receiver` for assets sent to the vault directly since the last call that
+    // moved assets, priced as if they hadn't arrived yet. No entry fee is taken. Whoever calls
+    // first claims the surplus, so the transfer and this call belong in the same transaction.
+    pub fn deposit_from_balance(env: Env, receiver: Address) -> Result<i128, Error> {
+        Self::check_open(&env)?;
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        let known = Self::last_known_assets(env.clone())?;
+        let surplus = total - known;
+        if surplus <= 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        Self::accrue_fees(&env, known)?;
+        Self::observe_price(&env, known)?;
+        Self::check_solvency(&env, known)?;
+        Self::check_deposit_limits(&env, &receiver, surplus, known)?;
+        let shares = Self::convert_to_shares_internal(&env, surplus, known, false)? - Self::dead_shares_due(&env);
+        if shares <= 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        Self::record_deposit(&env, &receiver, surplus)?;
+        Self::mint_deposit_shares(&env, &receiver, shares)?;
+        Self::set_known_assets(&env, total);
+        Self::publish_deposit(&env, receiver.clone(), receiver, surplus, shares);
+        Self::publish_rate(&env, total);
+        
+        Ok(shares)
+    }
+
+    // Sends `assets` to the vault without minting anything, so the whole amount accrues to
+    // existing holders. The sanctioned path for what `deposit` refuses with `ZeroShares`, and
+    // unlike a direct transfer it can't be claimed through `deposit_from_balance`.
+    pub fn donate(env: Env, donor: Address, assets: i128) -> Result<(), Error> {
+        donor.require_auth();
+        
+        Self::check_open(&env)?;
+        Self::check_access(&env, &donor)?;
+        
+        Self::require_non_negative(assets)?;
+        if assets == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        Self::accrue_fees(&env, total)?;
+        Self::observe_price(&env, total)?;
+        
+        Self::non_reentrant(&env, || {
+            let asset_client = token::Client::new(&env, &Self::asset(env.clone())?);
+            asset_client.transfer(&donor, &env.current_contract_address(), &assets);
+            let total = Self::total_assets(env.clone())?;
+            Self::set_known_assets(&env, total);
+            
+            env.events().publish((EVENT_VERSION, symbol_short!("donation"), donor.clone()), assets);
+            Self::publish_rate(&env, total);
+            Ok(())
+        })
+    }
+
+    // Rebasing assets: balance changes that arrive without a vault call belong to existing
+    // holders. Conversions already price against the live balance; with this on, each deposit,
+    // mint, withdraw or redeem also books the change on entry, so `deposit_from_balance`
+    // never sees a rebase as a direct transfer it could mint shares for.
+    pub fn rebasing(env: Env) -> bool {
+        env.storage().instance().get(&AccountingKey::RebasingAsset).unwrap_or(false)
+    }
+
+    pub fn set_rebasing(env: Env, enabled: bool) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&AccountingKey::RebasingAsset, &enabled);
+        Ok(())
+    }
+
+    pub fn rebase_checkpoint(env: Env) -> Option<Snapshot> {
+        env.storage().instance().get(&AccountingKey::RebaseCheckpoint)
+    }
+
+    pub fn last_known_assets(env: Env) -> Result<i128, Error> {
+        match env.storage().instance().get(&AccountingKey::LastKnownAssets) {
+            Some(known) => Ok(known),
+            None => Self::total_assets(env),
+        }
+    }
+
+    pub fn withdraw(env: Env, caller: Address, assets: i128, receiver: Address, owner: Address) -> Result<i128, Error> {
+        caller.require_auth();
+        
+        if Self::is_shutdown(env.clone()) {
+            return Err(Error::Shutdown);
+        }
+        
+        Self::require_non_negative(assets)?;
+        if assets == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        Self::accrue_fees(&env, total)?;
+        Self::observe_price(&env, total)?;
+        let shares = Self::preview_withdraw_internal(&env, assets, total)?;
+        if shares <= 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        Self::withdraw_internal(&env, caller, receiver, owner, assets, shares, total)?;
+        
+        Ok(shares)
+    }
+
+    pub fn redeem(env: Env, caller: Address, shares: i128, receiver: Address, owner: Address) -> Result<i128, Error> {
+        caller.require_auth();
+        Self::redeem_internal(&env, caller, shares, receiver, owner)
+    }
+
+    // Each `(shares, receiver, owner)` entry is redeemed in order as `caller`, spending
+    // `caller`'s allowance from owners other than itself. Any failing entry reverts the batch.
+    pub fn batch_redeem(env: Env, caller: Address, entries: Vec<(i128, Address, Address)>) -> Result<Vec<i128>, Error> {
+        caller.require_auth();
+        
+        let mut paid = Vec::new(&env);
+        for (shares, receiver, owner) in entries.iter() {
+            paid.push_back(Self::redeem_internal(&env, caller.clone(), shares, receiver, owner)?);
+        }
+        Ok(paid)
+    }
+
+    fn redeem_internal(env: &Env, caller: Address, shares: i128, receiver: Address, owner: Address) -> Result<i128, Error> {
+        Self::require_non_negative(shares)?;
+        if shares == 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(env, total);
+        Self::accrue_fees(env, total)?;
+        Self::observe_price(env, total)?;
+        let assets = Self::preview_redeem_internal(env, shares, total)?;
+        if assets <= 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        Self::withdraw_internal(env, caller, receiver, owner, assets, shares, total)?;
+        
+        Ok(assets)
+    }
+
+    // Redeems `owner`'s entire balance as of this call, so the position closes out even if
+    // the share price moves between reading the balance and submitting the transaction
+    pub fn redeem_all(env: Env, caller: Address, receiver: Address, owner: Address) -> Result<i128, Error> {
+        let shares = Self::balance_of(env.clone(), owner.clone());
+        Self::redeem(env, caller, shares, receiver, owner)
+    }
+
+    // Moves a position into another vault over the same asset in one call: redeems `shares` to
+    // `owner`, then deposits what was paid out into `target_vault` as `owner`, who receives the
+    // new shares. `owner` authorizes both legs. Returns the shares minted by the target.
+    pub fn redeem_and_deposit(env: Env, shares: i128, owner: Address, target_vault: Address) -> Result<i128, Error> {
+        owner.require_auth();
+        
+        if target_vault == env.current_contract_address() {
+            return Err(Error::InvalidAddress);
+        }
+        let target = VaultContractClient::new(&env, &target_vault);
+        match target.try_asset() {
+            Ok(Ok(asset)) if asset == Self::asset(env.clone())? => {}
+            _ => return Err(Error::AssetMismatch),
+        }
+        
+        let assets = Self::redeem_internal(&env, owner.clone(), shares, owner.clone(), owner.clone())?;
+        Ok(target.deposit(&owner, &assets, &owner))
+    }
+
+    // Burns `shares` now at the current rate and queues the payout for when enough assets are
+    // idle. Returns the request id to pass to `fulfill_withdraw`.
+    pub fn request_withdraw(
+        env: Env,
+        caller: Address,
+        shares: i128,
+        receiver: Address,
+        owner: Address,
+    ) -> Result<u32, Error> {
+        caller.require_auth();
+        
+        if Self::breaker_tripped(env.clone()) {
+            return Err(Error::Paused);
+        }
+        Self::require_non_negative(shares)?;
+        if shares == 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total);
+        Self::accrue_fees(&env, total)?;
+        Self::observe_price(&env, total)?;
+        let assets = Self::preview_redeem_internal(&env, shares, total)?;
+        if assets <= 0 {
+            return Err(Error::ZeroAssets);
+        }
+        let gross = Self::convert_to_assets_internal(&env, shares, total, false)?;
+        
+        Self::check_access(&env, &owner)?;
+        Self::check_cooldown(&env, &owner, shares)?;
+        if caller != owner {
+            Self::spend_allowance(&env, owner.clone(), caller.clone(), shares)?;
+        }
+        Self::burn_internal(&env, owner.clone(), shares)?;
+        Self::check_dust(&env, &owner)?;
+        Self::release_deposit(&env, &owner, assets);
+        
+        let pending = Self::pending_withdrawals(env.clone()).checked_add(gross).ok_or(Error::Overflow)?;
+        env.storage().instance().set(&DataKey::PendingWithdrawals, &pending);
+        Self::set_known_assets(&env, total - gross);
+        
+        let id: u32 = env.storage().instance().get(&DataKey::WithdrawRequestCount).unwrap_or(0);
+        env.storage().instance().set(&DataKey::WithdrawRequestCount, &(id + 1));
+        env.storage().persistent().set(
+            &DataKey::WithdrawRequest(id),
+            &WithdrawRequest {
+                owner: owner.clone(),
+                receiver: receiver.clone(),
+                shares,
+                assets,
+                fee: gross - assets,
+                ledger: env.ledger().sequence(),
+            }
+        );
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "withdraw_requested"), owner, receiver), (id, shares, assets));
+        Self::publish_rate(&env, total - gross);
+        Ok(id)
+    }
+
+    // Permissionless; pays out a queued request once the vault holds enough idle assets
+    pub fn fulfill_withdraw(env: Env, request_id: u32) -> Result<i128, Error> {
+        if Self::breaker_tripped(env.clone()) {
+            return Err(Error::Paused);
+        }
+        let key = DataKey::WithdrawRequest(request_id);
+        let request: WithdrawRequest = env.storage().persistent().get(&key).ok_or(Error::RequestNotFound)?;
+        let owed = request.assets + request.fee;
+        if owed > Self::idle_assets(env.clone())? {
+            return Err(Error::InsufficientIdleAssets);
+        }
+        
+        Self::non_reentrant(&env, || {
+            // Queued assets are already excluded from `total_assets`, so the baseline adds them back
+            let pending = Self::pending_withdrawals(env.clone());
+            let holdings = Self::total_assets(env.clone())?.checked_add(pending).ok_or(Error::Overflow)?;
+            Self::record_outflow(&env, owed, holdings)?;
+            
+            env.storage().persistent().remove(&key);
+            env.storage().instance().set(&DataKey::PendingWithdrawals, &(pending - owed));
+            
+            Self::pay_out(&env, &request.receiver, request.assets, request.fee)?;
+            
+            env.events().publish(
+                (EVENT_VERSION, Symbol::new(&env, "withdraw_fulfilled"), request.owner.clone(), request.receiver.clone()),
+                (request_id, request.assets)
+            );
+            Ok(request.assets)
+        })
+    }
+
+    pub fn withdraw_request(env: Env, request_id: u32) -> Option<WithdrawRequest> {
+        env.storage().persistent().get(&DataKey::WithdrawRequest(request_id))
+    }
+
+    // Backing relative to the shares outstanding valued at the initial 1:1 rate (adjusted for
+    // the decimals offset), in basis points. An empty vault reports 10000.
+    pub fn solvency_ratio(env: Env) -> Result<i128, Error> {
+        Self::solvency_ratio_internal(&env, Self::total_assets(env.clone())?)
+    }
+
+    // A vault is solvent while it holds any backing at all and, if a minimum is configured,
+    // while its ratio stays at or above it
+    pub fn is_solvent(env: Env) -> Result<bool, Error> {
+        Self::is_solvent_internal(&env, Self::total_assets(env.clone())?)
+    }
+
+    // Compares realizable backing with what the shares are booked at. Gains or losses the
+    // strategy hasn't been harvested for yet show up here before they reach the share price.
+    pub fn backing_report(env: Env) -> Result<BackingReport, Error> {
+        let booked = Self::total_assets(env.clone())?;
+        let total_supply = Self::total_supply(env.clone());
+        let total_assets = match Self::strategy(env.clone()) {
+            Some(strategy) => booked - Self::deployed_assets(env.clone()) + StrategyClient::new(&env, &strategy).report(),
+            None => booked,
+        };
+        let implied_assets = Self::convert_to_assets_internal(&env, total_supply, booked, false)?;
+        
+        Ok(BackingReport {
+            total_assets,
+            total_supply,
+            implied_assets,
+            gap: total_assets - implied_assets,
+        })
+    }
+
+    pub fn min_solvency_bps(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::MinSolvencyBps).unwrap_or(0)
+    }
+
+    // Deposits are refused while insolvent only once a nonzero minimum is set
+    pub fn set_min_solvency_bps(env: Env, min_bps: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinSolvencyBps, &min_bps);
+        Ok(())
+    }
+
+    // Assets owed to queued withdrawals that have not been fulfilled yet
+    pub fn pending_withdrawals(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::PendingWithdrawals).unwrap_or(0)
+    }
+
+    // (raw contract balance, pending withdrawals, deployed to strategy) for reconciliation. The
+    // raw balance still holds what queued withdrawals are owed; the deployed amount is not in it.
+    pub fn asset_balance_breakdown(env: Env) -> Result<(i128, i128, i128), Error> {
+        Ok((
+            Self::idle_assets(env.clone())?,
+            Self::pending_withdrawals(env.clone()),
+            Self::deployed_assets(env),
+        ))
+    }
+
+    // Every entry is priced at the rate before the batch; assets are pulled from `caller` once
+    pub fn batch_deposit(env: Env, caller: Address, entries: Vec<(i128, Address)>) -> Result<Vec<i128>, Error> {
+        caller.require_auth();
+        
+        Self::check_open(&env)?;
+        
+        let entry_fee_bps = Self::entry_fee_bps(env.clone());
+        let total_assets = Self::total_assets(env.clone())?;
+        Self::sync_rebase(&env, total_assets);
+        Self::accrue_fees(&env, total_assets)?;
+        Self::observe_price(&env, total_assets)?;
+        Self::check_solvency(&env, total_assets)?;
+        let mut total = 0i128;
+        let mut total_fee = 0i128;
+        let mut minted = Vec::new(&env);
+        let min_deposit = Self::min_deposit(env.clone());
+        for (assets, receiver) in entries.iter() {
+            Self::require_non_negative(assets)?;
+            if assets == 0 {
+                return Err(Error::ZeroAssets);
+            }
+            if assets < min_deposit {
+                return Err(Error::BelowMinimum);
+            }
+            Self::check_access(&env, &receiver)?;
+            
+            // Only the first entry pays for any dead shares
+            let fee = Self::fee_on_total(assets, entry_fee_bps)?;
+            let dead = if minted.is_empty() { Self::dead_shares_due(&env) } else { 0 };
+            let shares = Self::convert_to_shares_internal(&env, assets - fee, total_assets, false)? - dead;
+            if shares <= 0 {
+                return Err(Error::ZeroShares);
+            }
+            
+            // Recorded as each entry is priced so repeated receivers count against one cap;
+            // any later failure reverts these writes with the rest of the invocation
+            if assets > Self::user_deposit_remaining(&env, &receiver) {
+                return Err(Error::UserCapExceeded);
+            }
+            Self::record_deposit(&env, &receiver, assets)?;
+            
+            total = total.checked_add(assets).ok_or(Error::Overflow)?;
+            total_fee += fee;
+            minted.push_back(shares);
+        }
+        
+        if total > Self::global_deposit_remaining(&env, total_assets) {
+            return Err(Error::DepositCapExceeded);
+        }
+        
+        Self::non_reentrant(&env, || {
+            if total > 0 {
+                Self::collect(&env, &caller, total - total_fee, total_fee, false)?;
+                Self::set_known_assets(&env, total_assets + total - total_fee);
+            }
+            
+            for ((assets, receiver), shares) in entries.iter().zip(minted.iter()) {
+                Self::mint_deposit_shares(&env, &receiver, shares)?;
+                Self::publish_deposit(&env, caller.clone(), receiver, assets, shares);
+            }
+            Self::publish_rate(&env, total_assets + total - total_fee);
+            
+            Ok(minted.clone())
+        })
+    }
+
+    // A failed minimum reverts the whole invocation, including the transfers already made
+    pub fn deposit_with_min_shares(
+        env: Env,
+        caller: Address,
+        assets: i128,
+        receiver: Address,
+        min_shares: i128,
+    ) -> Result<i128, Error> {
+        let shares = Self::deposit(env, caller, assets, receiver)?;
+        if shares < min_shares {
+            return Err(Error::SlippageExceeded);
+        }
+        Ok(shares)
+    }
+
+    pub fn redeem_with_min_assets(
+        env: Env,
+        caller: Address,
+        shares: i128,
+        receiver: Address,
+        owner: Address,
+        min_assets: i128,
+    ) -> Result<i128, Error> {
+        let assets = Self::redeem(env, caller, shares, receiver, owner)?;
+        if assets < min_assets {
+            return Err(Error::SlippageExceeded);
+        }
+        Ok(assets)
+    }
+
+    // Refuse to run once the ledger is past `deadline_ledger`, so a transaction that sat
+    // unincluded isn't executed at a price the caller no longer expects
+    pub fn deposit_by(
+        env: Env,
+        caller: Address,
+        assets: i128,
+        receiver: Address,
+        deadline_ledger: u32,
+    ) -> Result<i128, Error> {
+        Self::check_deadline(&env, deadline_ledger)?;
+        Self::deposit(env, caller, assets, receiver)
+    }
+
+    pub fn redeem_by(
+        env: Env,
+        caller: Address,
+        shares: i128,
+        receiver: Address,
+        owner: Address,
+        deadline_ledger: u32,
+    ) -> Result<i128, Error> {
+        Self::check_deadline(&env, deadline_ledger)?;
+        Self::redeem(env, caller, shares, receiver, owner)
+    }
+
+    pub fn deposit_cap(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::DepositCap).unwrap_or(i128::MAX)
+    }
+
+    pub fn set_deposit_cap(env: Env, cap: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::DepositCap, &cap);
+        Ok(())
+    }
+
+    // Basket
+    // Additional tokens held by the vault that count towards `total_assets`. Deposits and
+    // withdrawals stay in `asset`; with nothing registered the vault behaves as single-asset.
+    // `weight` converts one unit of the token into `asset` units, in basis points.
+    pub fn add_asset(env: Env, asset: Address, weight: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        if asset == Self::asset(env.clone())? {
+            return Err(Error::InvalidAddress);
+        }
+        if weight == 0 {
+            return Err(Error::InvalidWeight);
+        }
+        
+        let key = DataKey::AssetEntry(asset.clone());
+        if !env.storage().instance().has(&key) {
+            let mut list = Self::basket(&env);
+            list.push_back(asset.clone());
+            env.storage().instance().set(&DataKey::AssetList, &list);
+        }
+        env.storage().instance().set(&key, &weight);
+        let total = Self::total_assets(env.clone())?;
+        Self::set_known_assets(&env, total);
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "asset_added"), asset), weight);
+        Self::publish_rate(&env, total);
+        Ok(())
+    }
+
+    pub fn remove_asset(env: Env, asset: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        let key = DataKey::AssetEntry(asset.clone());
+        if !env.storage().instance().has(&key) {
+            return Err(Error::AssetNotRegistered);
+        }
+        
+        let mut list = Self::basket(&env);
+        if let Some(index) = list.first_index_of(&asset) {
+            list.remove(index);
+        }
+        env.storage().instance().set(&DataKey::AssetList, &list);
+        env.storage().instance().remove(&key);
+        let total = Self::total_assets(env.clone())?;
+        Self::set_known_assets(&env, total);
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "asset_removed"), asset), ());
+        Self::publish_rate(&env, total);
+        Ok(())
+    }
+
+    // The primary asset first, followed by registered basket assets
+    pub fn assets(env: Env) -> Result<Vec<Address>, Error> {
+        let mut assets = Vec::new(&env);
+        assets.push_back(Self::asset(env.clone())?);
+        assets.append(&Self::basket(&env));
+        Ok(assets)
+    }
+
+    pub fn asset_weight(env: Env, asset: Address) -> Result<Option<u32>, Error> {
+        if asset == Self::asset(env.clone())? {
+            return Ok(Some(BPS_DENOMINATOR as u32));
+        }
+        Ok(env.storage().instance().get(&DataKey::AssetEntry(asset)))
+    }
+
+    // Oracle
+    pub fn oracle(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Oracle)
+    }
+
+    pub fn set_oracle(env: Env, oracle: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::Oracle, &oracle);
+        Ok(())
+    }
+
+    // `total_assets` in quote units, or in asset units when no oracle is set
+    pub fn total_assets_in_quote(env: Env) -> Result<i128, Error> {
+        let total = Self::total_assets(env.clone())?;
+        Self::to_quote(&env, total)
+    }
+
+    // `price_per_share` in quote units, or in asset units when no oracle is set
+    pub fn price_per_share_in_quote(env: Env) -> Result<i128, Error> {
+        let price = Self::price_per_share(env.clone())?;
+        Self::to_quote(&env, price)
+    }
+
+    // Mints shares with no matching deposit, e.g. to seed a treasury at launch. They dilute
+    // every other holder: the assets behind each share drop by the minted fraction and later
+    // deposits are priced at the diluted rate. Bounded in total by `max_unbacked`.
+    pub fn admin_mint(env: Env, to: Address, shares: i128) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        Self::require_non_negative(shares)?;
+        if shares == 0 {
+            return Err(Error::ZeroShares);
+        }
+        
+        let unbacked = Self::unbacked_shares(env.clone()).checked_add(shares).ok_or(Error::Overflow)?;
+        if unbacked > Self::max_unbacked(env.clone()) {
+            return Err(Error::UnbackedCapExceeded);
+        }
+        env.storage().instance().set(&DataKey::UnbackedShares, &unbacked);
+        
+        Self::mint_internal(&env, to.clone(), shares)?;
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "admin_mint"), admin, to), shares);
+        Self::publish_rate(&env, Self::total_assets(env.clone())?);
+        Ok(())
+    }
+
+    // Total shares minted through `admin_mint`
+    pub fn unbacked_shares(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::UnbackedShares).unwrap_or(0)
+    }
+
+    // Zero by default, which disables `admin_mint`
+    pub fn max_unbacked(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MaxUnbacked).unwrap_or(0)
+    }
+
+    pub fn set_max_unbacked(env: Env, max_unbacked: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MaxUnbacked, &max_unbacked);
+        Ok(())
+    }
+
+    pub fn strategy(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Strategy)
+    }
+
+    pub fn set_strategy(env: Env, strategy: Address) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::Strategy, &strategy);
+        Ok(())
+    }
+
+    pub fn deploy_to_strategy(env: Env, amount: i128) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        let strategy = Self::strategy(env.clone()).ok_or(Error::StrategyNotSet)?;
+        
+        Self::require_non_negative(amount)?;
+        if amount == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        // Assets set aside for queued withdrawals stay in the vault
+        if amount > Self::idle_available(&env, Self::total_assets(env.clone())?)? {
+            return Err(Error::InsufficientIdleAssets);
+        }
+        
+        let deployed = Self::deployed_assets(env.clone()).checked_add(amount).ok_or(Error::Overflow)?;
+        
+        Self::non_reentrant(&env, || {
+            let asset_address = Self::asset(env.clone())?;
+            token::Client::new(&env, &asset_address).transfer(&env.current_contract_address(), &strategy, &amount);
+            StrategyClient::new(&env, &strategy).deposit(&amount);
+            
+            env.storage().instance().set(&DataKey::DeployedAssets, &deployed);
+            env.events().publish((EVENT_VERSION, symbol_short!("deploy"), admin.clone(), strategy.clone()), amount);
+            Ok(())
+        })
+    }
+
+    // Recalls deployed principal, for paying out exits and queued withdrawals. Profit above
+    // the deployed amount is brought back with `harvest` instead.
+    pub fn withdraw_from_strategy(env: Env, amount: i128) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        let strategy = Self::strategy(env.clone()).ok_or(Error::StrategyNotSet)?;
+        
+        Self::require_non_negative(amount)?;
+        if amount == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        let deployed = Self::deployed_assets(env.clone());
+        if amount > deployed {
+            return Err(Error::InsufficientVaultAssets);
+        }
+        
+        Self::non_reentrant(&env, || {
+            Self::recall(&env, &strategy, amount)?;
+            
+            env.storage().instance().set(&DataKey::DeployedAssets, &(deployed - amount));
+            env.events().publish((EVENT_VERSION, symbol_short!("recall"), admin.clone(), strategy.clone()), amount);
+            Ok(())
+        })
+    }
+
+    // Pulls any profit above the deployed amount back into the vault and books losses
+    // against `deployed_assets`, so `total_assets` reflects the strategy's latest report.
+    // Returns the profit harvested.
+    pub fn harvest(env: Env) -> Result<i128, Error> {
+        let admin = Self::require_admin(&env)?;
+        let strategy = Self::strategy(env.clone()).ok_or(Error::StrategyNotSet)?;
+        
+        Self::non_reentrant(&env, || {
+            let strategy_client = StrategyClient::new(&env, &strategy);
+            let reported = strategy_client.report();
+            let deployed = Self::deployed_assets(env.clone());
+            
+            let profit = (reported - deployed).max(0);
+            let loss = (deployed - reported).max(0);
+            if profit > 0 {
+                Self::recall(&env, &strategy, profit)?;
+            }
+            // A loss is written down in full and lowers the share price; no fee is taken on it
+            env.storage().instance().set(&DataKey::DeployedAssets, &(reported - profit));
+            if loss == 0 {
+                Self::take_performance_fee(&env)?;
+            }
+            let total_assets_after = Self::total_assets(env.clone())?;
+            Self::set_known_assets(&env, total_assets_after);
+            
+            env.events().publish(
+                (EVENT_VERSION, Symbol::new(&env, "harvested"), admin.clone(), strategy.clone()),
+                HarvestEvent {
+                    profit,
+                    loss,
+                    total_assets_after,
+                }
+            );
+            Self::publish_rate(&env, total_assets_after);
+            Ok(profit)
+        })
+    }
+
+    // Off-chain valued holdings, counted in `total_assets` but never paid out directly. Each
+    // report may move the figure by at most `max_report_delta_bps` of `total_assets`; until
+    // the admin sets a nonzero bound, reports are refused.
+    pub fn reported_assets(env: Env) -> i128 {
+        env.storage().instance().get(&AccountingKey::ReportedAssets).unwrap_or(0)
+    }
+
+    pub fn max_report_delta_bps(env: Env) -> u32 {
+        env.storage().instance().get(&GuardKey::MaxReportDeltaBps).unwrap_or(0)
+    }
+
+    pub fn set_max_report_delta_bps(env: Env, max_bps: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&GuardKey::MaxReportDeltaBps, &max_bps);
+        Ok(())
+    }
+
+    pub fn report_assets(env: Env, reporter: Address, reported_total: i128) -> Result<(), Error> {
+        Self::require_role(&env, REPORTER, &reporter)?;
+        Self::require_non_negative(reported_total)?;
+        
+        let total = Self::total_assets(env.clone())?;
+        let previous = Self::reported_assets(env.clone());
+        let max_bps = Self::max_report_delta_bps(env.clone());
+        if max_bps == 0 {
+            return Err(Error::ReportBoundNotSet);
+        }
+        let limit = Self::mul_div(total, max_bps as i128, BPS_DENOMINATOR, false)?;
+        if (reported_total - previous).abs() > limit {
+            return Err(Error::ReportDeltaTooLarge);
+        }
+        
+        let total_after = total
+            .checked_sub(previous)
+            .and_then(|total| total.checked_add(reported_total))
+            .ok_or(Error::Overflow)?;
+        env.storage().instance().set(&AccountingKey::ReportedAssets, &reported_total);
+        Self::set_known_assets(&env, total_after);
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(&env, "assets_reported"), reporter), (previous, reported_total));
+        Self::publish_rate(&env, total_after);
+        Ok(())
+    }
+
+    // Reports assets held outside the vault so they still count towards `total_assets`
+    pub fn set_deployed_assets(env: Env, amount: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        Self::require_non_negative(amount)?;
+        env.storage().instance().set(&DataKey::DeployedAssets, &amount);
+        let total = Self::total_assets(env.clone())?;
+        Self::set_known_assets(&env, total);
+        Self::publish_rate(&env, total);
+        Ok(())
+    }
+
+    // Lends idle assets to `receiver` for the duration of its `on_flash_loan` callback. The fee
+    // stays in the vault, raising the share price for existing holders.
+    pub fn flash_loan(env: Env, receiver: Address, amount: i128, data: Bytes) -> Result<i128, Error> {
+        Self::check_open(&env)?;
+        Self::require_non_negative(amount)?;
+        if amount == 0 {
+            return Err(Error::ZeroAssets);
+        }
+        
+        let total = Self::total_assets(env.clone())?;
+        if amount > Self::idle_available(&env, total)? {
+            return Err(Error::InsufficientIdleAssets);
+        }
+        let fee = Self::mul_div(amount, Self::flash_fee_bps(env.clone()) as i128, BPS_DENOMINATOR, true)?;
+        
+        Self::non_reentrant(&env, || {
+            let asset_address = Self::asset(env.clone())?;
+            let asset_client = token::Client::new(&env, &asset_address);
+            let before = asset_client.balance(&env.current_contract_address());
+            
+            asset_client.transfer(&env.current_contract_address(), &receiver, &amount);
+            FlashBorrowerClient::new(&env, &receiver).on_flash_loan(&asset_address, &amount, &fee, &data);
+            
+            let after = asset_client.balance(&env.current_contract_address());
+            if after < before.checked_add(fee).ok_or(Error::Overflow)? {
+                return Err(Error::FlashLoanNotRepaid);
+            }
+            let total_after = total.checked_add(after - before).ok_or(Error::Overflow)?;
+            Self::set_known_assets(&env, total_after);
+            
+            env.events().publish((EVENT_VERSION, Symbol::new(&env, "flash_loan"), receiver.clone()), (amount, fee));
+            Self::publish_rate(&env, total_after);
+            Ok(fee)
+        })
+    }
+
+    // Smallest amount of assets `deposit`, `mint` and `batch_deposit` entries accept
+    pub fn min_deposit(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MinDeposit).unwrap_or(0)
+    }
+
+    pub fn set_min_deposit(env: Env, min_deposit: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinDeposit, &min_deposit);
+        Ok(())
+    }
+
+    // Smallest nonzero share balance a withdrawal may leave behind; below it the owner has
+    // to exit in full
+    pub fn min_balance(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MinBalance).unwrap_or(0)
+    }
+
+    pub fn set_min_balance(env: Env, min_balance: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinBalance, &min_balance);
+        Ok(())
+    }
+
+    // Per-address limit on cumulative deposited assets, net of withdrawals
+    pub fn user_deposit_cap(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::UserDepositCap).unwrap_or(i128::MAX)
+    }
+
+    pub fn set_user_deposit_cap(env: Env, cap: i128) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::UserDepositCap, &cap);
+        Ok(())
+    }
+
+    pub fn user_deposited(env: Env, user: Address) -> i128 {
+        env.storage().instance().get(&DataKey::UserDeposited(user)).unwrap_or(0)
+    }
+
+    // Ledgers shares must sit in an account after arriving, by deposit or transfer, before they
+    // can be withdrawn or moved on; zero disables the check
+    pub fn cooldown_ledgers(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::CooldownLedgers).unwrap_or(0)
+    }
+
+    pub fn set_cooldown_ledgers(env: Env, ledgers: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::CooldownLedgers, &ledgers);
+        Ok(())
+    }
+
+    // Burns MINIMUM_LIQUIDITY shares on the first deposit; has no effect once shares exist
+    pub fn dead_shares(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::DeadShares).unwrap_or(false)
+    }
+
+    pub fn set_dead_shares(env: Env, enabled: bool) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::DeadShares, &enabled);
+        Ok(())
+    }
+
+    // Fees
+    pub fn entry_fee_bps(env: Env) -> u32 {
+        Self::load_config(&env).map_or(0, |config| config.entry_fee_bps)
+    }
+
+    pub fn exit_fee_bps(env: Env) -> u32 {
+        Self::load_config(&env).map_or(0, |config| config.exit_fee_bps)
+    }
+
+    pub fn fee_recipient(env: Env) -> Result<Address, Error> {
+        match env.storage().instance().get(&DataKey::FeeRecipient) {
+            Some(recipient) => Ok(recipient),
+            None => Self::admin(env),
+        }
+    }
+
+    pub fn fee_config(env: Env) -> Result<FeeConfig, Error> {
+        Ok(FeeConfig {
+            entry_bps: Self::entry_fee_bps(env.clone()),
+            exit_bps: Self::exit_fee_bps(env.clone()),
+            performance_bps: Self::performance_fee_bps(env.clone()),
+            management_bps: Self::management_fee_bps(env.clone()),
+            flash_bps: Self::flash_fee_bps(env.clone()),
+            recipient: Self::fee_recipient(env)?,
+        })
+    }
+
+    // Cumulative (entry, exit, performance) fees in assets. Management fees aren't included,
+    // and neither are flash loan fees, which stay with holders and emit no `fee_collected`.
+    pub fn fees_collected(env: Env) -> (i128, i128, i128) {
+        let read = |key: DataKey| -> i128 { env.storage().instance().get(&key).unwrap_or(0) };
+        (
+            read(DataKey::TotalEntryFees),
+            read(DataKey::TotalExitFees),
+            read(DataKey::TotalPerfFees),
+        )
+    }
+
+    pub fn set_entry_fee(env: Env, caller: Address, fee_bps: u32) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        Self::update_config(&env, |config| config.entry_fee_bps = fee_bps)?;
+        Ok(())
+    }
+
+    pub fn set_exit_fee(env: Env, caller: Address, fee_bps: u32) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        Self::update_config(&env, |config| config.exit_fee_bps = fee_bps)?;
+        Ok(())
+    }
+
+    pub fn performance_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::PerformanceFeeBps).unwrap_or(0)
+    }
+
+    pub fn set_performance_fee(env: Env, caller: Address, fee_bps: u32) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        if fee_bps > MAX_PERFORMANCE_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        env.storage().instance().set(&DataKey::PerformanceFeeBps, &fee_bps);
+        Ok(())
+    }
+
+    // Annual rate, accrued per ledger as shares minted to the fee recipient
+    pub fn management_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::ManagementFeeBps).unwrap_or(0)
+    }
+
+    // Settles what accrued at the old rate before switching
+    pub fn set_management_fee(env: Env, caller: Address, fee_bps: u32) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        Self::accrue_fees(&env, Self::total_assets(env.clone())?)?;
+        env.storage().instance().set(&DataKey::ManagementFeeBps, &fee_bps);
+        Ok(())
+    }
+
+    pub fn flash_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&FeeKey::FlashFeeBps).unwrap_or(0)
+    }
+
+    pub fn set_flash_fee(env: Env, caller: Address, fee_bps: u32) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+        env.storage().instance().set(&FeeKey::FlashFeeBps, &fee_bps);
+        Ok(())
+    }
+
+    // Highest price per share a performance fee has been charged up to; starts at one whole asset
+    pub fn high_water_mark(env: Env) -> Result<i128, Error> {
+        match env.storage().instance().get(&DataKey::HighWaterMark) {
+            Some(mark) => Ok(mark),
+            None => Self::one_asset(&env),
+        }
+    }
+
+    pub fn set_fee_recipient(env: Env, caller: Address, recipient: Address) -> Result<(), Error> {
+        Self::require_role(&env, FEE_MANAGER, &caller)?;
+        env.storage().instance().set(&DataKey::FeeRecipient, &recipient);
+        Ok(())
+    }
+
+    // Recovers tokens sent to the vault by mistake; backing and basket assets can never be swept
+    pub fn sweep(env: Env, token_addr: Address, to: Address, amount: i128) -> Result<(), Error> {
+        let admin = Self::require_admin(&env)?;
+        
+        if token_addr == Self::asset(env.clone())?
+            || env.storage().instance().has(&DataKey::AssetEntry(token_addr.clone()))
+        {
+            return Err(Error::CannotSweepAsset);
+        }
+        
+        token::Client::new(&env, &token_addr).transfer(&env.current_contract_address(), &to, &amount);
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("sweep"), admin, token_addr), (to, amount));
+        Ok(())
+    }
+
+    // Checkpoints
+    // Permissionless; records the exchange rate at the current ledger and returns its index.
+    // Only the last `checkpoint_window` checkpoints are kept.
+    pub fn checkpoint(env: Env) -> Result<u32, Error> {
+        let index = Self::checkpoint_count(env.clone());
+        let checkpoint = Checkpoint {
+            ledger: env.ledger().sequence(),
+            total_assets: Self::total_assets(env.clone())?,
+            total_supply: Self::total_supply(env.clone()),
+        };
+        env.storage().persistent().set(&DataKey::Checkpoint(index), &checkpoint);
+        env.storage().instance().set(&DataKey::CheckpointCount, &(index + 1));
+        
+        let window = Self::checkpoint_window(env.clone());
+        if index >= window {
+            env.storage().persistent().remove(&DataKey::Checkpoint(index - window));
+        }
+        
+        Ok(index)
+    }
+
+    // Number of checkpoints ever recorded, including evicted ones
+    pub fn checkpoint_count(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::CheckpointCount).unwrap_or(0)
+    }
+
+    pub fn get_checkpoint(env: Env, index: u32) -> Option<Checkpoint> {
+        env.storage().persistent().get(&DataKey::Checkpoint(index))
+    }
+
+    pub fn latest_checkpoint(env: Env) -> Option<Checkpoint> {
+        match Self::checkpoint_count(env.clone()) {
+            0 => None,
+            count => Self::get_checkpoint(env, count - 1),
+        }
+    }
+
+    pub fn checkpoint_window(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::CheckpointWindow).unwrap_or(DEFAULT_CHECKPOINT_WINDOW)
+    }
+
+    // Shrinking the window doesn't evict retroactively; entries beyond it age out one per checkpoint
+    pub fn set_checkpoint_window(env: Env, window: u32) -> Result<(), Error> {
+        Self::require_admin(&env)?;
+        if window == 0 {
+            return Err(Error::InvalidCheckpointWindow);
+        }
+        env.storage().instance().set(&DataKey::CheckpointWindow, &window);
+        Ok(())
+    }
+
+    // `assets` includes the entry fee, which is sent on to the fee recipient
+    fn deposit_internal(
+        env: &Env,
+        caller: Address,
+        receiver: Address,
+        assets: i128,
+        shares: i128,
+        total: i128,
+    ) -> Result<(), Error> {
+        Self::non_reentrant(env, || {
+            let net = Self::transfer_in(env, &caller, assets, false)?;
+            Self::set_known_assets(env, total + net);
+            
+            Self::record_deposit(env, &receiver, assets)?;
+            Self::mint_deposit_shares(env, &receiver, shares)?;
+            Self::publish_deposit(env, caller, receiver, assets, shares);
+            Self::publish_rate(env, total + net);
+            
+            Ok(())
+        })
+    }
+
+    // Pulls `assets` from `from`, routing the entry fee to the fee recipient; returns the net
+    fn transfer_in(env: &Env, from: &Address, assets: i128, use_allowance: bool) -> Result<i128, Error> {
+        let fee = Self::fee_on_total(assets, Self::entry_fee_bps(env.clone()))?;
+        Self::collect(env, from, assets - fee, fee, use_allowance)?;
+        Ok(assets - fee)
+    }
+
+    // Ordering: deposits pull assets before minting and exits burn before paying out, so a
+    // token call never runs while shares exist that aren't backed yet, or still exist after
+    // being paid for. Everything the transfers need is read up front; nothing after them reads
+    // state they could have changed, apart from `pull_assets` measuring what arrived.
+    // With `use_allowance` the vault spends its allowance from `from` instead of relying on
+    // `from`'s auth for the transfers
+    fn collect(env: &Env, from: &Address, net: i128, fee: i128, use_allowance: bool) -> Result<(), Error> {
+        let asset_address = Self::asset(env.clone())?;
+        let fee_recipient = Self::fee_recipient(env.clone())?;
+        Self::record_fee(env, DataKey::TotalEntryFees, symbol_short!("entry"), fee)?;
+        let asset_client = token::Client::new(env, &asset_address);
+        let vault = env.current_contract_address();
+        let transfer = |to: &Address, amount: i128| {
+            if use_allowance {
+                asset_client.transfer_from(&vault, from, to, &amount);
+            } else {
+                asset_client.transfer(from, to, &amount);
+            }
+        };
+        transfer(&vault, net);
+        if fee > 0 {
+            transfer(&fee_recipient, fee);
+        }
+        Ok(())
+    }
+
+    fn pay_out(env: &Env, to: &Address, assets: i128, fee: i128) -> Result<(), Error> {
+        let asset_address = Self::asset(env.clone())?;
+        let fee_recipient = Self::fee_recipient(env.clone())?;
+        Self::record_fee(env, DataKey::TotalExitFees, symbol_short!("exit"), fee)?;
+        let asset_client = token::Client::new(env, &asset_address);
+        asset_client.transfer(&env.current_contract_address(), to, &assets);
+        if fee > 0 {
+            asset_client.transfer(&env.current_contract_address(), &fee_recipient, &fee);
+        }
+        Ok(())
+    }
+
+    fn record_fee(env: &Env, key: DataKey, kind: Symbol, fee: i128) -> Result<(), Error> {
+        if fee <= 0 {
+            return Ok(());
+        }
+        let total: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        let total = total.checked_add(fee).ok_or(Error::Overflow)?;
+        env.storage().instance().set(&key, &total);
+        env.events().publish((EVENT_VERSION, Symbol::new(env, "fee_collected"), kind), fee);
+        Ok(())
+    }
+
+    // Returns the assets the vault actually received, net of the entry fee
+    fn pull_assets(env: &Env, from: &Address, assets: i128, total_before: i128, use_allowance: bool) -> Result<i128, Error> {
+        Self::transfer_in(env, from, assets, use_allowance)?;
+        let total = Self::total_assets(env.clone())?;
+        Self::set_known_assets(env, total);
+        Ok(total - total_before)
+    }
+
+    // Vaults still on the pre-2 layout read their settings from the separate keys until migrated
+    fn load_config(env: &Env) -> Option<Config> {
+        env.storage().instance().get(&ConfigKey::Config).or_else(|| Self::legacy_config(env))
+    }
+
+    fn legacy_config(env: &Env) -> Option<Config> {
+        let storage = env.storage().instance();
+        Some(Config {
+            admin: storage.get(&DataKey::Admin)?,
+            asset: storage.get(&DataKey::Asset)?,
+            name: storage.get(&DataKey::Name)?,
+            symbol: storage.get(&DataKey::Symbol)?,
+            decimals: storage.get(&DataKey::Decimals)?,
+            decimals_offset: storage.get(&DataKey::DecimalsOffset).unwrap_or(0),
+            entry_fee_bps: storage.get(&DataKey::EntryFeeBps).unwrap_or(0),
+            exit_fee_bps: storage.get(&DataKey::ExitFeeBps).unwrap_or(0),
+        })
+    }
+
+    fn update_config(env: &Env, update: impl FnOnce(&mut Config)) -> Result<(), Error> {
+        let mut config = Self::config(env.clone())?;
+        update(&mut config);
+        env.storage().instance().set(&ConfigKey::Config, &config);
+        Ok(())
+    }
+
+    fn set_known_assets(env: &Env, total: i128) {
+        env.storage().instance().set(&AccountingKey::LastKnownAssets, &total);
+    }
+
+    fn sync_rebase(env: &Env, total: i128) {
+        if !Self::rebasing(env.clone()) {
+            return;
+        }
+        let known: i128 = env.storage().instance().get(&AccountingKey::LastKnownAssets).unwrap_or(total);
+        if total == known {
+            return;
+        }
+        Self::set_known_assets(env, total);
+        env.storage().instance().set(
+            &AccountingKey::RebaseCheckpoint,
+            &Snapshot {
+                ledger: env.ledger().sequence(),
+                value: total,
+            }
+        );
+        
+        env.events().publish((EVENT_VERSION, symbol_short!("rebase")), (total - known, total));
+    }
+
+    fn publish_deposit(env: &Env, caller: Address, receiver: Address, assets: i128, shares: i128) {
+        env.events().publish(
+            (EVENT_VERSION, symbol_short!("deposit"), caller.clone(), receiver.clone()),
+            DepositEvent {
+                sender: caller,
+                owner: receiver,
+                assets,
+                shares,
+            }
+        );
+    }
+
+    // Announces (total assets, total supply) after a call that can move the exchange rate,
+    // unless the assets behind one whole share are the same as at the last announcement.
+    // Fees accrued on entry are picked up here along with the call's own effect. A vault too
+    // large to price one whole share without overflow announces every change.
+    fn publish_rate(env: &Env, total: i128) {
+        let supply = Self::total_supply(env.clone());
+        let rate = if supply == 0 {
+            Self::one_asset(env).ok()
+        } else {
+            Self::one_share(env)
+                .and_then(|one| Self::convert_to_assets_with(env, one, total, supply, false))
+                .ok()
+        };
+        match rate {
+            Some(rate) if env.storage().instance().get(&AccountingKey::LastRate) == Some(rate) => return,
+            Some(rate) => env.storage().instance().set(&AccountingKey::LastRate, &rate),
+            None => env.storage().instance().remove(&AccountingKey::LastRate),
+        }
+        
+        env.events().publish((EVENT_VERSION, Symbol::new(env, "rate_updated")), (total, supply));
+    }
+
+    // `assets` excludes the exit fee, which is paid out of the vault on top
+    fn withdraw_internal(
+        env: &Env,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        assets: i128,
+        shares: i128,
+        total: i128,
+    ) -> Result<(), Error> {
+        Self::non_reentrant(env, || {
+            if Self::breaker_tripped(env.clone()) {
+                return Err(Error::Paused);
+            }
+            Self::check_access(env, &owner)?;
+            Self::check_cooldown(env, &owner, shares)?;
+            
+            let fee = Self::fee_on_raw(assets, Self::exit_fee(env))?;
+            let owed = assets.checked_add(fee).ok_or(Error::Overflow)?;
+            if owed > total {
+                return Err(Error::InsufficientVaultAssets);
+            }
+            // Deployed and basket assets count towards the share price but can't be paid out directly
+            if owed > Self::idle_available(env, total)? {
+                return Err(Error::InsufficientIdleAssets);
+            }
+            
+            if caller != owner {
+                Self::spend_allowance(env, owner.clone(), caller.clone(), shares)?;
+            }
+            
+            Self::burn_internal(env, owner.clone(), shares)?;
+            Self::check_dust(env, &owner)?;
+            Self::release_deposit(env, &owner, assets);
+            Self::record_outflow(env, owed, total)?;
+            Self::set_known_assets(env, total - owed);
+            
+            Self::pay_out(env, &receiver, assets, fee)?;
+            
+            env.events().publish(
+                (EVENT_VERSION, symbol_short!("withdraw"), caller.clone(), owner.clone()),
+                WithdrawEvent {
+                    sender: caller,
+                    receiver,
+                    owner,
+                    assets,
+                    shares,
+                }
+            );
+            
+            Self::publish_rate(env, total - owed);
+            
+            Ok(())
+        })
+    }
+
+    // The withdrawal that crosses the limit still completes; the pause applies from the next one
+    fn record_outflow(env: &Env, owed: i128, total: i128) -> Result<(), Error> {
+        let max_bps = Self::max_outflow_per_ledger(env.clone());
+        if max_bps == 0 {
+            return Ok(());
+        }
+        
+        let key = GuardKey::LedgerOutflow(env.ledger().sequence());
+        let (baseline, outflow): (i128, i128) = env.storage().temporary().get(&key).unwrap_or((total, 0));
+        let outflow = outflow.checked_add(owed).ok_or(Error::Overflow)?;
+        env.storage().temporary().set(&key, &(baseline, outflow));
+        
+        let limit = Self::mul_div(baseline, max_bps as i128, BPS_DENOMINATOR, false)?;
+        if outflow > limit && !Self::breaker_tripped(env.clone()) {
+            env.storage().instance().set(&DataKey::Paused, &true);
+            env.storage().instance().set(&GuardKey::Tripped, &true);
+            env.events().publish((EVENT_VERSION, Symbol::new(env, "auto_paused")), (outflow, baseline));
+        }
+        Ok(())
+    }
+
+    // Asks the strategy for `amount` and fails unless at least that much actually arrived
+    fn recall(env: &Env, strategy: &Address, amount: i128) -> Result<(), Error> {
+        let asset_client = token::Client::new(env, &Self::asset(env.clone())?);
+        let before = asset_client.balance(&env.current_contract_address());
+        StrategyClient::new(env, strategy).withdraw(&amount);
+        let after = asset_client.balance(&env.current_contract_address());
+        if after < before.checked_add(amount).ok_or(Error::Overflow)? {
+            return Err(Error::StrategyShortfall);
+        }
+        Ok(())
+    }
+
+    // The host already rejects contract re-entry; this guards the external token calls
+    // regardless of that runtime behavior.
+    fn non_reentrant<T>(env: &Env, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        if env.storage().temporary().has(&DataKey::ReentrancyGuard) {
+            return Err(Error::Reentrancy);
+        }
+        env.storage().temporary().set(&DataKey::ReentrancyGuard, &true);
+        
+        let result = f();
+        
+        env.storage().temporary().remove(&DataKey::ReentrancyGuard);
+        result
+    }
+
+    fn prefixed(env: &Env, value: &String) -> Option<String> {
+        let len = value.len() as usize;
+        if len == 0 || len >= MAX_DERIVED_METADATA_LEN {
+            return None;
+        }
+        let mut buf = [0u8; MAX_DERIVED_METADATA_LEN];
+        buf[0] = b'v';
+        value.copy_into_slice(&mut buf[1..=len]);
+        Some(String::from_bytes(env, &buf[..=len]))
+    }
+
+    fn require_admin(env: &Env) -> Result<Address, Error> {
+        let admin = Self::admin(env.clone())?;
+        admin.require_auth();
+        Ok(admin)
+    }
+
+    // Every balance and allowance check is a `<` comparison, which a negative amount passes and
+    // then runs backwards: a negative transfer would pull shares from `to`, a negative burn mint
+    fn require_non_negative(amount: i128) -> Result<(), Error> {
+        if amount < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        Ok(())
+    }
+
+    fn check_deadline(env: &Env, deadline_ledger: u32) -> Result<(), Error> {
+        if env.ledger().sequence() > deadline_ledger {
+            return Err(Error::DeadlinePassed);
+        }
+        Ok(())
+    }
+
+    fn check_open(env: &Env) -> Result<(), Error> {
+        if Self::is_shutdown(env.clone()) {
+            return Err(Error::Shutdown);
+        }
+        if Self::paused(env.clone()) {
+            return Err(Error::Paused);
+        }
+        Ok(())
+    }
+
+    // Exit fee actually charged; waived once the vault is shut down
+    fn exit_fee(env: &Env) -> u32 {
+        if Self::is_shutdown(env.clone()) {
+            0
+        } else {
+            Self::exit_fee_bps(env.clone())
+        }
+    }
+
+    fn require_role(env: &Env, role: Symbol, who: &Address) -> Result<(), Error> {
+        who.require_auth();
+        if *who != Self::admin(env.clone())? && !Self::has_role(env.clone(), role, who.clone()) {
+            return Err(Error::Unauthorized);
+        }
+        Ok(())
+    }
+
+    // Expired allowances read as zero; `u32::MAX` means the allowance never expires
+    fn allowance_value(env: &Env, owner: Address, spender: Address) -> AllowanceValue {
+        let allowance: Option<AllowanceValue> = env.storage().instance().get(&DataKey::Allowance(owner, spender));
+        match allowance {
+            Some(allowance) if allowance.expiration_ledger >= env.ledger().sequence() => allowance,
+            _ => AllowanceValue {
+                amount: 0,
+                expiration_ledger: u32::MAX,
+            },
+        }
+    }
+
+    // Returns the current nonce and advances it, so each signed message is accepted only once
+    fn use_nonce(env: &Env, owner: &Address) -> u64 {
+        let key = DataKey::Nonce(owner.clone());
+        let nonce = Self::nonces(env.clone(), owner.clone());
+        env.storage().persistent().set(&key, &(nonce + 1));
+        env.storage().persistent().extend_ttl(&key, NONCE_TTL_THRESHOLD, NONCE_TTL_EXTEND_TO);
+        nonce
+    }
+
+    // An account address is the strkey of its Ed25519 key: a version byte, the 32-byte key and
+    // a checksum, base32-encoded into 56 characters starting with 'G'
+    fn account_public_key(env: &Env, owner: &Address) -> Result<BytesN<32>, Error> {
+        let strkey = owner.to_string();
+        if strkey.len() != 56 {
+            return Err(Error::InvalidAddress);
+        }
+        let mut chars = [0u8; 56];
+        strkey.copy_into_slice(&mut chars);
+        if chars[0] != b'G' {
+            return Err(Error::InvalidAddress);
+        }
+        
+        let mut decoded = [0u8; 35];
+        let mut buffer = 0u32;
+        let mut bits = 0u32;
+        let mut index = 0;
+        for c in chars {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'2'..=b'7' => c - b'2' + 26,
+                _ => return Err(Error::InvalidAddress),
+            };
+            buffer = (buffer << 5) | value as u32;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                decoded[index] = (buffer >> bits) as u8;
+                buffer &= (1 << bits) - 1;
+                index += 1;
+            }
+        }
+        
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&decoded[1..33]);
+        Ok(BytesN::from_array(env, &key))
+    }
+
+    fn approve_internal(env: &Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        env.storage().instance().set(
+            &DataKey::Allowance(from.clone(), spender.clone()),
+            &AllowanceValue {
+                amount,
+                expiration_ledger,
+            }
+        );
+        
+        if amount == i128::MAX {
+            env.events().publish((EVENT_VERSION, Symbol::new(env, "approve_infinite"), from, spender), expiration_ledger);
+        } else {
+            env.events().publish(
+                (EVENT_VERSION, symbol_short!("approve"), from, spender),
+                amount
+            );
+        }
+    }
+
+    fn spend_allowance(env: &Env, owner: Address, spender: Address, amount: i128) -> Result<(), Error> {
+        let allowance = Self::allowance_value(env, owner.clone(), spender.clone());
+        if allowance.amount < amount {
+            return Err(Error::InsufficientAllowance);
+        }
+        
+        // Goes through `approve_internal` so every decrement publishes the new allowance
+        if allowance.amount != i128::MAX {
+            Self::approve_internal(env, owner, spender, allowance.amount - amount, allowance.expiration_ledger);
+        }
+        
+        Ok(())
+    }
+
+    // Self-transfers are rejected rather than treated as no-ops so that a mistaken `to` fails
+    // loudly instead of emitting a transfer event that moved nothing. Shares sent to the vault
+    // itself could never be moved again, so it is rejected as a recipient too.
+    fn transfer_internal(env: &Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+        Self::require_non_negative(amount)?;
+        if from == to {
+            return Err(Error::SelfTransfer);
+        }
+        if to == env.current_contract_address() {
+            return Err(Error::InvalidAddress);
+        }
+        if Self::is_blocked(env.clone(), from.clone()) || Self::is_blocked(env.clone(), to.clone()) {
+            return Err(Error::AddressBlocked);
+        }
+        
+        let from_balance = Self::balance_of(env.clone(), from.clone());
+        if from_balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        Self::check_cooldown(env, &from, amount)?;
+        
+        Self::set_balance(env, &from, from_balance, from_balance - amount);
+        
+        let to_balance = Self::balance_of(env.clone(), to.clone());
+        Self::set_balance(env, &to, to_balance, to_balance + amount);
+        Self::lock_received(env, &to, amount);
+        
+        env.events().publish(
+            (EVENT_VERSION, symbol_short!("transfer"), from, to),
+            (amount, Self::next_event_seq(env))
+        );
+        
+        Ok(())
+    }
+
+    // Writes a balance and keeps the holder index in step when it crosses zero
+    fn set_balance(env: &Env, account: &Address, old_balance: i128, new_balance: i128) {
+        env.storage().instance().set(&DataKey::Balance(account.clone()), &new_balance);
+        Self::push_snapshot(
+            env,
+            AccountingKey::BalanceCheckpointCount(account.clone()),
+            |index| AccountingKey::BalanceCheckpoint(account.clone(), index),
+            new_balance,
+        );
+        
+        if old_balance <= 0 && new_balance > 0 {
+            Self::add_holder(env, account);
+        } else if old_balance > 0 && new_balance <= 0 {
+            Self::remove_holder(env, account);
+        }
+    }
+
+    fn set_total_supply(env: &Env, total_supply: i128) {
+        env.storage().instance().set(&DataKey::TotalSupply, &total_supply);
+        Self::push_snapshot(env, AccountingKey::SupplyCheckpointCount, AccountingKey::SupplyCheckpoint, total_supply);
+    }
+
+    // Appends to a history, overwriting the last entry if it was written in this ledger
+    fn push_snapshot(env: &Env, count_key: AccountingKey, entry: impl Fn(u32) -> AccountingKey, value: i128) {
+        let storage = env.storage().persistent();
+        let ledger = env.ledger().sequence();
+        let count: u32 = storage.get(&count_key).unwrap_or(0);
+        if count > 0 {
+            let last: Snapshot = storage.get(&entry(count - 1)).unwrap();
+            if last.ledger == ledger {
+                storage.set(&entry(count - 1), &Snapshot { ledger, value });
+                storage.extend_ttl(&entry(count - 1), HISTORY_TTL_THRESHOLD, HISTORY_TTL_EXTEND_TO);
+                return;
+            }
+        }
+        storage.set(&entry(count), &Snapshot { ledger, value });
+        storage.set(&count_key, &(count + 1));
+        storage.extend_ttl(&entry(count), HISTORY_TTL_THRESHOLD, HISTORY_TTL_EXTEND_TO);
+        storage.extend_ttl(&count_key, HISTORY_TTL_THRESHOLD, HISTORY_TTL_EXTEND_TO);
+    }
+
+    // Binary search for the last entry at or before `ledger`; zero before the first one
+    fn snapshot_at(env: &Env, count_key: AccountingKey, entry: impl Fn(u32) -> AccountingKey, ledger: u32) -> i128 {
+        let storage = env.storage().persistent();
+        let (mut low, mut high) = (0u32, storage.get(&count_key).unwrap_or(0u32));
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let snapshot: Snapshot = storage.get(&entry(mid)).unwrap();
+            if snapshot.ledger > ledger {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        if low == 0 {
+            return 0;
+        }
+        storage.get::<_, Snapshot>(&entry(low - 1)).unwrap().value
+    }
+
+    fn add_holder(env: &Env, account: &Address) {
+        let count = Self::holder_count(env);
+        env.storage().instance().set(&DataKey::HolderIndex(count), account);
+        env.storage().instance().set(&DataKey::HolderExists(account.clone()), &count);
+        env.storage().instance().set(&DataKey::HolderCount, &(count + 1));
+    }
+
+    // Swaps the last holder into the removed slot so the index stays dense
+    fn remove_holder(env: &Env, account: &Address) {
+        let storage = env.storage().instance();
+        let position: u32 = match storage.get(&DataKey::HolderExists(account.clone())) {
+            Some(position) => position,
+            None => return,
+        };
+        let last = Self::holder_count(env) - 1;
+        if position != last {
+            let moved: Address = storage.get(&DataKey::HolderIndex(last)).unwrap();
+            storage.set(&DataKey::HolderIndex(position), &moved);
+            storage.set(&DataKey::HolderExists(moved), &position);
+        }
+        storage.remove(&DataKey::HolderIndex(last));
+        storage.remove(&DataKey::HolderExists(account.clone()));
+        storage.set(&DataKey::HolderCount, &last);
+    }
+
+    fn holder_count(env: &Env) -> u32 {
+        env.storage().instance().get(&DataKey::HolderCount).unwrap_or(0)
+    }
+
+    fn next_event_seq(env: &Env) -> u64 {
+        let seq = Self::current_event_seq(env.clone()) + 1;
+        env.storage().instance().set(&DataKey::EventSeq, &seq);
+        seq
+    }
+
+    fn dead_shares_due(env: &Env) -> i128 {
+        if Self::dead_shares(env.clone()) && !env.storage().instance().has(&DataKey::FirstDeposit) {
+            MINIMUM_LIQUIDITY
+        } else {
+            0
+        }
+    }
+
+    // The vault never authorizes spending its own shares, so anything minted to it stays locked
+    fn mint_deposit_shares(env: &Env, receiver: &Address, shares: i128) -> Result<(), Error> {
+        let dead = Self::dead_shares_due(env);
+        if dead > 0 {
+            Self::mint_internal(env, env.current_contract_address(), dead)?;
+        }
+        env.storage().instance().set(&DataKey::FirstDeposit, &true);
+        
+        Self::mint_internal(env, receiver.clone(), shares)?;
+        Self::lock_received(env, receiver, shares);
+        Ok(())
+    }
+
+    // No balance can exceed the supply, so checking the supply covers both
+    fn mint_internal(env: &Env, account: Address, amount: i128) -> Result<(), Error> {
+        let total_supply = Self::total_supply(env.clone())
+            .checked_add(amount)
+            .ok_or(Error::SupplyOverflow)?;
+        let balance = Self::balance_of(env.clone(), account.clone());
+        Self::set_balance(env, &account, balance, balance + amount);
+        Self::set_total_supply(env, total_supply);
+        
+        env.events().publish(
+            (EVENT_VERSION, symbol_short!("mint"), account),
+            (amount, Self::next_event_seq(env))
+        );
+        Ok(())
+    }
+
+    fn burn_internal(env: &Env, account: Address, amount: i128) -> Result<(), Error> {
+        Self::require_non_negative(amount)?;
+        let balance = Self::balance_of(env.clone(), account.clone());
+        if balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        
+        // With dead shares on, an empty vault would reprice the next deposit at 1:1. Dead shares
+        // normally keep the supply above zero; this covers vaults that enabled them after their
+        // first deposit. Holders can still exit in full once the vault is shut down.
+        let total_supply = Self::total_supply(env.clone()) - amount;
+        if total_supply == 0
+            && Self::dead_shares(env.clone())
+            && env.storage().instance().has(&DataKey::FirstDeposit)
+            && !Self::is_shutdown(env.clone())
+        {
+            return Err(Error::WouldEmptyVault);
+        }
+        
+        Self::set_balance(env, &account, balance, balance - amount);
+        Self::set_total_supply(env, total_supply);
+        
+        env.events().publish(
+            (EVENT_VERSION, symbol_short!("burn"), account),
+            (amount, Self::next_event_seq(env))
+        );
+        
+        Ok(())
+    }
+
+    // Virtual shares and assets (OpenZeppelin ERC4626): the `10^offset` virtual shares and
+    // the single virtual asset make a donation to an empty vault unprofitable for the donor.
+    // Every `total_assets` read is a cross-contract `balance` call, so public entry points read
+    // it once and pass it down to the helpers below.
+    //
+    // Rounding always favors the vault: shares received round down, shares owed round up,
+    // assets owed round up and assets paid out round down.
+    fn preview_deposit_internal(env: &Env, assets: i128, total: i128) -> Result<i128, Error> {
+        // Shares minted for `assets`: round down
+        let fee = Self::fee_on_total(assets, Self::entry_fee_bps(env.clone()))?;
+        Ok(Self::convert_to_shares_internal(env, assets - fee, total, false)? - Self::dead_shares_due(env))
+    }
+
+    fn preview_mint_internal(env: &Env, shares: i128, total: i128) -> Result<i128, Error> {
+        // Assets pulled for `shares`: round up, plus any dead shares still owed
+        let gross = shares.checked_add(Self::dead_shares_due(env)).ok_or(Error::Overflow)?;
+        let assets = Self::convert_to_assets_internal(env, gross, total, true)?;
+        let fee = Self::fee_on_raw(assets, Self::entry_fee_bps(env.clone()))?;
+        assets.checked_add(fee).ok_or(Error::Overflow)
+    }
+
+    fn preview_withdraw_internal(env: &Env, assets: i128, total: i128) -> Result<i128, Error> {
+        // Shares burned for `assets`: round up
+        let fee = Self::fee_on_raw(assets, Self::exit_fee(env))?;
+        Self::convert_to_shares_internal(env, assets.checked_add(fee).ok_or(Error::Overflow)?, total, true)
+    }
+
+    fn preview_redeem_internal(env: &Env, shares: i128, total: i128) -> Result<i128, Error> {
+        // Assets paid for `shares`: round down
+        let assets = Self::convert_to_assets_internal(env, shares, total, false)?;
+        let fee = Self::fee_on_total(assets, Self::exit_fee(env))?;
+        Ok(assets - fee)
+    }
+
+    fn max_deposit_internal(env: &Env, receiver: &Address, total: i128) -> i128 {
+        Self::global_deposit_remaining(env, total).min(Self::user_deposit_remaining(env, receiver))
+    }
+
+    fn global_deposit_remaining(env: &Env, total: i128) -> i128 {
+        Self::deposit_cap(env.clone()).saturating_sub(total).max(0)
+    }
+
+    fn user_deposit_remaining(env: &Env, user: &Address) -> i128 {
+        Self::user_deposit_cap(env.clone()).saturating_sub(Self::user_deposited(env.clone(), user.clone())).max(0)
+    }
+
+    // Mints the management fee for the ledgers since the last accrual, prorated on `total`.
+    // Runs at the start of every flow that moves assets or shares; a second call in the same
+    // ledger accrues nothing.
+    fn accrue_fees(env: &Env, total: i128) -> Result<(), Error> {
+        let now = env.ledger().sequence();
+        let last: u32 = env.storage().instance().get(&DataKey::LastFeeAccrual).unwrap_or(now);
+        if now <= last {
+            return Ok(());
+        }
+        env.storage().instance().set(&DataKey::LastFeeAccrual, &now);
+        
+        let fee_bps = Self::management_fee_bps(env.clone()) as i128;
+        let supply = Self::total_supply(env.clone());
+        if fee_bps == 0 || supply == 0 || total <= 0 || Self::is_shutdown(env.clone()) {
+            return Ok(());
+        }
+        
+        let elapsed = (now - last) as i128;
+        let fee = Self::mul_div(
+            total,
+            fee_bps.checked_mul(elapsed).ok_or(Error::Overflow)?,
+            BPS_DENOMINATOR * LEDGERS_PER_YEAR,
+            false,
+        )?;
+        let shares = Self::fee_shares(env, fee, total, supply)?;
+        if shares > 0 {
+            Self::mint_internal(env, Self::fee_recipient(env.clone())?, shares)?;
+        }
+        Ok(())
+    }
+
+    // Shares worth `fee` once minted: fee * (supply + virtual) / (total - fee + 1)
+    fn fee_shares(env: &Env, fee: i128, total: i128, supply: i128) -> Result<i128, Error> {
+        if fee <= 0 || fee >= total {
+            return Ok(0);
+        }
+        Self::mul_div(
+            fee,
+            supply.checked_add(Self::virtual_shares(env)?).ok_or(Error::Overflow)?,
+            total - fee + 1,
+            false,
+        )
+    }
+
+    // Charges `performance_fee_bps` of the gain above the high-water mark by minting shares to
+    // the fee recipient, then moves the mark to the post-fee price. Gains that only recover
+    // earlier losses stay below the mark and are not charged again.
+    fn take_performance_fee(env: &Env) -> Result<(), Error> {
+        let total = Self::total_assets(env.clone())?;
+        let supply = Self::total_supply(env.clone());
+        let mark = Self::high_water_mark(env.clone())?;
+        let price = Self::price_per_share_internal(env, total)?;
+        if supply == 0 || price <= mark {
+            return Ok(());
+        }
+        
+        let profit = Self::mul_div(price - mark, supply, Self::one_share(env)?, false)?;
+        let fee = Self::mul_div(profit, Self::performance_fee_bps(env.clone()) as i128, BPS_DENOMINATOR, false)?;
+        let shares = Self::fee_shares(env, fee, total, supply)?;
+        if shares > 0 {
+            Self::record_fee(env, DataKey::TotalPerfFees, symbol_short!("perf"), fee)?;
+            Self::mint_internal(env, Self::fee_recipient(env.clone())?, shares)?;
+        }
+        
+        let new_mark = Self::price_per_share_internal(env, total)?;
+        env.storage().instance().set(&DataKey::HighWaterMark, &new_mark);
+        Ok(())
+    }
+
+    fn to_quote(env: &Env, assets: i128) -> Result<i128, Error> {
+        let oracle = match Self::oracle(env.clone()) {
+            Some(oracle) => oracle,
+            None => return Ok(assets),
+        };
+        let oracle_client = OracleClient::new(env, &oracle);
+        let price = oracle_client.price(&Self::asset(env.clone())?);
+        let scale = 10i128.checked_pow(oracle_client.decimals()).ok_or(Error::Overflow)?;
+        Self::mul_div(assets, price, scale, false)
+    }
+
+    fn basket(env: &Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::AssetList).unwrap_or(Vec::new(env))
+    }
+
+    // Value of basket holdings in `asset` units; one `balance` call per registered asset
+    fn basket_assets(env: &Env) -> Result<i128, Error> {
+        let mut value = 0i128;
+        for asset in Self::basket(env).iter() {
+            let balance = token::Client::new(env, &asset).balance(&env.current_contract_address());
+            let worth = Self::mul_div(balance, Self::basket_price(env, &asset), BPS_DENOMINATOR, false)?;
+            value = value.checked_add(worth).ok_or(Error::Overflow)?;
+        }
+        Ok(value)
+    }
+
+    // Price hook normalizing a basket asset into `asset` units, in basis points
+    fn basket_price(env: &Env, asset: &Address) -> i128 {
+        env.storage().instance().get::<_, u32>(&DataKey::AssetEntry(asset.clone())).unwrap_or(0) as i128
+    }
+
+    // Queued payouts are fixed when requested, so a strategy loss after that can leave them
+    // owed more than the vault holds; the total floors at zero rather than going negative
+    fn total_from_idle(env: &Env, idle: i128) -> Result<i128, Error> {
+        let held = idle
+            .checked_add(Self::deployed_assets(env.clone()))
+            .and_then(|total| total.checked_add(Self::reported_assets(env.clone())))
+            .ok_or(Error::Overflow)?;
+        held.checked_add(Self::basket_assets(env)?)
+            .and_then(|total| total.checked_sub(Self::pending_withdrawals(env.clone())))
+            .map(|total| total.max(0))
+            .ok_or(Error::Overflow)
+    }
+
+    // What can be paid out immediately: `total` minus deployed and basket holdings
+    fn idle_available(env: &Env, total: i128) -> Result<i128, Error> {
+        Ok(total - Self::deployed_assets(env.clone()) - Self::reported_assets(env.clone()) - Self::basket_assets(env)?)
+    }
+
+    // Largest payout whose exit fee still fits in the idle assets alongside it
+    fn idle_payable(env: &Env, total: i128) -> Result<i128, Error> {
+        let idle = Self::idle_available(env, total)?.max(0);
+        let fee_bps = Self::exit_fee(env) as i128;
+        Self::mul_div(idle, BPS_DENOMINATOR, BPS_DENOMINATOR + fee_bps, false)
+    }
+
+    fn solvency_ratio_internal(env: &Env, total: i128) -> Result<i128, Error> {
+        let supply = Self::total_supply(env.clone());
+        if supply == 0 {
+            return Ok(BPS_DENOMINATOR);
+        }
+        let scale = Self::virtual_shares(env)?.checked_mul(BPS_DENOMINATOR).ok_or(Error::Overflow)?;
+        Self::mul_div(total.max(0), scale, supply, false)
+    }
+
+    fn is_solvent_internal(env: &Env, total: i128) -> Result<bool, Error> {
+        let ratio = Self::solvency_ratio_internal(env, total)?;
+        Ok(ratio > 0 && ratio >= Self::min_solvency_bps(env.clone()) as i128)
+    }
+
+    fn check_solvency(env: &Env, total: i128) -> Result<(), Error> {
+        if Self::min_solvency_bps(env.clone()) > 0 && !Self::is_solvent_internal(env, total)? {
+            return Err(Error::Insolvent);
+        }
+        Ok(())
+    }
+
+    fn check_dust(env: &Env, owner: &Address) -> Result<(), Error> {
+        let remaining = Self::balance_of(env.clone(), owner.clone());
+        if remaining > 0 && remaining < Self::min_balance(env.clone()) {
+            return Err(Error::BelowMinimum);
+        }
+        Ok(())
+    }
+
+    fn check_deposit_limits(env: &Env, receiver: &Address, assets: i128, total: i128) -> Result<(), Error> {
+        Self::check_access(env, receiver)?;
+        // Refused up front rather than left to overflow in the token or the share math
+        total.checked_add(assets).ok_or(Error::Overflow)?;
+        if assets < Self::min_deposit(env.clone()) {
+            return Err(Error::BelowMinimum);
+        }
+        if assets > Self::global_deposit_remaining(env, total) {
+            return Err(Error::DepositCapExceeded);
+        }
+        if assets > Self::user_deposit_remaining(env, receiver) {
+            return Err(Error::UserCapExceeded);
+        }
+        Ok(())
+    }
+
+    // Tracked against the receiver, who owns the shares and is the one to withdraw them
+    fn record_deposit(env: &Env, receiver: &Address, assets: i128) -> Result<(), Error> {
+        let deposited = Self::user_deposited(env.clone(), receiver.clone());
+        let updated = deposited.checked_add(assets).ok_or(Error::Overflow)?;
+        env.storage().instance().set(&DataKey::UserDeposited(receiver.clone()), &updated);
+        Ok(())
+    }
+
+    // Whether `who` may take shares from the vault or hand them back
+    fn check_access(env: &Env, who: &Address) -> Result<(), Error> {
+        if Self::is_blocked(env.clone(), who.clone()) {
+            return Err(Error::AddressBlocked);
+        }
+        if Self::allowlist_enabled(env.clone()) && !Self::is_allowlisted(env.clone(), who.clone()) {
+            return Err(Error::NotAllowlisted);
+        }
+        Ok(())
+    }
+
+    // Shares received within the cooldown can't leave `owner` by exit or by transfer until it
+    // lapses. Only what arrived is locked, and each receipt unlocks on its own schedule, so
+    // shares sent to someone never freeze or extend what they already held.
+    fn check_cooldown(env: &Env, owner: &Address, shares: i128) -> Result<(), Error> {
+        let locked = Self::locked_shares(env, owner);
+        if locked > 0 && shares > Self::balance_of(env.clone(), owner.clone()) - locked {
+            return Err(Error::Cooldown);
+        }
+        Ok(())
+    }
+
+    fn locked_shares(env: &Env, account: &Address) -> i128 {
+        Self::cooldown_lots(env, account)
+            .iter()
+            .fold(0i128, |locked, (_, shares)| locked.saturating_add(shares))
+    }
+
+    // Receipts whose cooldown hasn't lapsed yet
+    fn cooldown_lots(env: &Env, account: &Address) -> Vec<(u32, i128)> {
+        let cooldown = Self::cooldown_ledgers(env.clone());
+        let mut lots = Vec::new(env);
+        if cooldown == 0 {
+            return lots;
+        }
+        let stored: Vec<(u32, i128)> = env.storage().instance()
+            .get(&GuardKey::CooldownLock(account.clone()))
+            .unwrap_or(Vec::new(env));
+        for (ledger, shares) in stored.iter() {
+            if env.ledger().sequence() < ledger.saturating_add(cooldown) {
+                lots.push_back((ledger, shares));
+            }
+        }
+        lots
+    }
+
+    // Deposits minted to `account` and transfers into it both start the cooldown. Once
+    // `MAX_COOLDOWN_LOTS` receipts are live the newest absorbs the next, which only ever delays
+    // shares received after every older lot.
+    fn lock_received(env: &Env, account: &Address, shares: i128) {
+        if Self::cooldown_ledgers(env.clone()) == 0 {
+            return;
+        }
+        let now = env.ledger().sequence();
+        let mut lots = Self::cooldown_lots(env, account);
+        match lots.last() {
+            Some((ledger, locked)) if ledger == now || lots.len() >= MAX_COOLDOWN_LOTS => {
+                lots.set(lots.len() - 1, (now, locked.saturating_add(shares)));
+            }
+            _ => lots.push_back((now, shares)),
+        }
+        env.storage().instance().set(&GuardKey::CooldownLock(account.clone()), &lots);
+    }
+
+    // Withdrawals can exceed the tracked amount once yield accrues, so this floors at zero
+    fn release_deposit(env: &Env, owner: &Address, assets: i128) {
+        let deposited = Self::user_deposited(env.clone(), owner.clone());
+        env.storage().instance().set(&DataKey::UserDeposited(owner.clone()), &(deposited - assets).max(0));
+    }
+
+    fn price_per_share_internal(env: &Env, total: i128) -> Result<i128, Error> {
+        if Self::total_supply(env.clone()) == 0 {
+            return Self::one_asset(env);
+        }
+        Self::convert_to_assets_internal(env, Self::one_share(env)?, total, false)
+    }
+
+    fn convert_to_shares_internal(env: &Env, assets: i128, total: i128, round_up: bool) -> Result<i128, Error> {
+        let supply = Self::total_supply(env.clone());
+        Self::convert_to_shares_with(env, assets, Self::pricing_assets(env, total, supply)?, supply, round_up)
+    }
+
+    fn convert_to_assets_internal(env: &Env, shares: i128, total: i128, round_up: bool) -> Result<i128, Error> {
+        let supply = Self::total_supply(env.clone());
+        Self::convert_to_assets_with(env, shares, Self::pricing_assets(env, total, supply)?, supply, round_up)
+    }
+
+    // Stands in for `total` in conversions: the assets `supply` would be backed by at the
+    // smoothed per-share value
+    fn pricing_assets(env: &Env, total: i128, supply: i128) -> Result<i128, Error> {
+        if supply == 0 || Self::price_smoothing(env.clone()) == 0 {
+            return Ok(total);
+        }
+        let smoothed: Snapshot = match env.storage().instance().get(&AccountingKey::SmoothedAssets) {
+            Some(smoothed) => smoothed,
+            None => return Ok(total),
+        };
+        let scaled_supply = supply.checked_add(Self::virtual_shares(env)?).ok_or(Error::Overflow)?;
+        Ok((Self::mul_div(smoothed.value, scaled_supply, Self::one_share(env)?, false)? - 1).max(0))
+    }
+
+    // Once per ledger, before any pricing, so repeated calls can't walk the average
+    fn observe_price(env: &Env, total: i128) -> Result<(), Error> {
+        let weight = Self::price_smoothing(env.clone()) as i128;
+        let ledger = env.ledger().sequence();
+        let previous: Option<Snapshot> = env.storage().instance().get(&AccountingKey::SmoothedAssets);
+        if weight == 0 || previous.as_ref().is_some_and(|smoothed| smoothed.ledger == ledger) {
+            return Ok(());
+        }
+        
+        // An empty vault has no price to track; the first observation after it fills starts fresh
+        let supply = Self::total_supply(env.clone());
+        if supply == 0 {
+            env.storage().instance().remove(&AccountingKey::SmoothedAssets);
+            return Ok(());
+        }
+        let live = Self::convert_to_assets_with(env, Self::one_share(env)?, total, supply, false)?;
+        let value = match previous {
+            Some(smoothed) => smoothed.value + Self::mul_div(live - smoothed.value, weight, BPS_DENOMINATOR, false)?,
+            None => live,
+        };
+        env.storage().instance().set(&AccountingKey::SmoothedAssets, &Snapshot { ledger, value });
+        Ok(())
+    }
+
+    // `round_up` is the direction that favors the vault; the configured mode never applies here
+    fn convert_to_shares_with(env: &Env, assets: i128, total: i128, supply: i128, round_up: bool) -> Result<i128, Error> {
+        Self::convert_to_shares_with_mode(env, assets, total, supply, Self::favoring_vault(round_up))
+    }
+
+    fn convert_to_assets_with(env: &Env, shares: i128, total: i128, supply: i128, round_up: bool) -> Result<i128, Error> {
+        Self::convert_to_assets_with_mode(env, shares, total, supply, Self::favoring_vault(round_up))
+    }
+
+    fn convert_to_shares_with_mode(env: &Env, assets: i128, total: i128, supply: i128, mode: RoundingMode) -> Result<i128, Error> {
+        let virtual_shares = Self::virtual_shares(env)?;
+        
+        Self::mul_div_with(
+            assets,
+            supply.checked_add(virtual_shares).ok_or(Error::Overflow)?,
+            total.checked_add(1).ok_or(Error::Overflow)?,
+            mode,
+        )
+    }
+
+    fn convert_to_assets_with_mode(env: &Env, shares: i128, total: i128, supply: i128, mode: RoundingMode) -> Result<i128, Error> {
+        let virtual_shares = Self::virtual_shares(env)?;
+        
+        Self::mul_div_with(
+            shares,
+            total.checked_add(1).ok_or(Error::Overflow)?,
+            supply.checked_add(virtual_shares).ok_or(Error::Overflow)?,
+            mode,
+        )
+    }
+
+    // One whole share in base units
+    fn one_share(env: &Env) -> Result<i128, Error> {
+        10i128
+            .checked_pow(Self::decimals(env.clone()))
+            .ok_or(Error::Overflow)
+    }
+
+    // One whole asset in base units
+    fn one_asset(env: &Env) -> Result<i128, Error> {
+        10i128
+            .checked_pow(Self::asset_decimals(env.clone()))
+            .ok_or(Error::Overflow)
+    }
+
+    fn virtual_shares(env: &Env) -> Result<i128, Error> {
+        10i128
+            .checked_pow(Self::decimals_offset(env.clone()))
+            .ok_or(Error::Overflow)
+    }
+
+    // Fee charged on top of an amount that excludes it
+    fn fee_on_raw(assets: i128, fee_bps: u32) -> Result<i128, Error> {
+        Self::mul_div(assets, fee_bps as i128, BPS_DENOMINATOR, true)
+    }
+
+    // Fee part of an amount that already includes it
+    fn fee_on_total(assets: i128, fee_bps: u32) -> Result<i128, Error> {
+        Self::mul_div(assets, fee_bps as i128, fee_bps as i128 + BPS_DENOMINATOR, true)
+    }
+
+    fn check_overrides(total_assets: i128, total_supply: i128) -> Result<(), Error> {
+        Self::require_non_negative(total_assets)?;
+        Self::require_non_negative(total_supply)
+    }
+
+    // Only the `convert_to_*` views take the configured mode; rounding a deposit or exit any
+    // other way than in the vault's favor would pay users out of other holders' assets
+    fn view_rounding(env: &Env) -> RoundingMode {
+        Self::rounding_mode(env.clone()).unwrap_or(RoundingMode::Floor)
+    }
+
+    fn favoring_vault(round_up: bool) -> RoundingMode {
+        if round_up {
+            RoundingMode::Ceil
+        } else {
+            RoundingMode::Floor
+        }
+    }
+
+    fn mul_div(x: i128, y: i128, denominator: i128, round_up: bool) -> Result<i128, Error> {
+        Self::mul_div_with(x, y, denominator, Self::favoring_vault(round_up))
+    }
+
+    fn mul_div_with(x: i128, y: i128, denominator: i128, mode: RoundingMode) -> Result<i128, Error> {
+        let product = x.checked_mul(y).ok_or(Error::Overflow)?;
+        let result = product / denominator;
+        let remainder = product % denominator;
+        let round_up = match mode {
+            RoundingMode::Floor => false,
+            RoundingMode::Ceil => remainder > 0,
+            RoundingMode::HalfUp => remainder > 0 && remainder >= denominator - remainder,
+        };
+        if round_up {
+            Ok(result + 1)
+        } else {
+            Ok(result)
+        }
+    }
+}
+
+mod test;
@@ -1,13 +1,19 @@
 #![cfg(test)]
 
+extern crate std;
+
 use super::*;
+use ed25519_dalek::{Signer, SigningKey};
 use soroban_sdk::{
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
-    Address, Env, String, Symbol,
+    testutils::{
+        storage::Instance as _, storage::Persistent as _, Address as _, AuthorizedFunction, AuthorizedInvocation, Events as _,
+        Ledger as _, MockAuth, MockAuthInvoke,
+    },
+    vec, Address, Bytes, Env, IntoVal, String, Symbol, Vec,
 };
 
 mod token {
-    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Symbol, panic_with_error};
+    use soroban_sdk::{contract, contracterror, contractimpl, contracttype, Address, Env, String, panic_with_error};
 
     #[contracttype]
     pub enum DataKey {  
@@ -16,7 +22,9 @@ mod token {
         Allowance(Address, Address),
     }
 
-    #[contracttype]
+    #[contracterror]
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    #[repr(u32)]
     pub enum Error {
         InsufficientBalance = 1,
         InsufficientAllowance = 2,
@@ -39,6 +47,18 @@ mod token {
             env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
         }
 
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
+
+        pub fn name(env: Env) -> String {
+            String::from_str(&env, "Mock Token")
+        }
+
+        pub fn symbol(env: Env) -> String {
+            String::from_str(&env, "MOCK")
+        }
+
         pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
             from.require_auth();
             
@@ -53,6 +73,34 @@ mod token {
             env.storage().instance().set(&DataKey::Balance(to.clone()), &(to_balance + amount));
         }
 
+        pub fn approve(env: Env, from: Address, spender: Address, amount: i128, _expiration_ledger: u32) {
+            from.require_auth();
+            env.storage().instance().set(&DataKey::Allowance(from, spender), &amount);
+        }
+
+        pub fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+            env.storage().instance().get(&DataKey::Allowance(from, spender)).unwrap_or(0)
+        }
+
+        pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+            spender.require_auth();
+            
+            let allowance = Self::allowance(env.clone(), from.clone(), spender.clone());
+            if allowance < amount {
+                panic_with_error!(&env, Error::InsufficientAllowance);
+            }
+            env.storage().instance().set(&DataKey::Allowance(from.clone(), spender), &(allowance - amount));
+            
+            let from_balance = Self::balance(env.clone(), from.clone());
+            if from_balance < amount {
+                panic_with_error!(&env, Error::InsufficientBalance);
+            }
+            env.storage().instance().set(&DataKey::Balance(from), &(from_balance - amount));
+            
+            let to_balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&DataKey::Balance(to), &(to_balance + amount));
+        }
+
         pub fn mint(env: Env, to: Address, amount: i128) {
             let balance = Self::balance(env.clone(), to.clone());
             env.storage().instance().set(&DataKey::Balance(to.clone()), &(balance + amount));
@@ -60,6 +108,320 @@ mod token {
             let total_supply = Self::total_supply(env.clone());
             env.storage().instance().set(&DataKey::TotalSupply, &(total_supply + amount));
         }
+
+        // Grows `account`'s balance by `bps` in place, the way a rebasing token does
+        pub fn rebase(env: Env, account: Address, bps: i128) {
+            let balance = Self::balance(env.clone(), account.clone());
+            Self::mint(env, account, balance * bps / 10_000);
+        }
+    }
+}
+
+mod reentrant_token {
+    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Balance(Address),
+        Target,
+        ReentryFailed,
+    }
+
+    // Tries to re-enter the vault's `withdraw` whenever the vault pays out
+    #[contract]
+    pub struct ReentrantToken;
+
+    #[contractimpl]
+    impl ReentrantToken {
+        pub fn set_target(env: Env, vault: Address, attacker: Address) {
+            env.storage().instance().set(&DataKey::Target, &(vault, attacker));
+        }
+
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
+
+        pub fn reentry_failed(env: Env) -> bool {
+            env.storage().instance().get(&DataKey::ReentryFailed).unwrap_or(false)
+        }
+
+        pub fn balance(env: Env, account: Address) -> i128 {
+            env.storage().instance().get(&DataKey::Balance(account)).unwrap_or(0)
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&DataKey::Balance(to), &(balance + amount));
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+
+            let from_balance = Self::balance(env.clone(), from.clone());
+            env.storage().instance().set(&DataKey::Balance(from.clone()), &(from_balance - amount));
+            let to_balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&DataKey::Balance(to), &(to_balance + amount));
+
+            if let Some((vault, attacker)) = env.storage().instance().get::<_, (Address, Address)>(&DataKey::Target) {
+                if from == vault {
+                    let vault_client = crate::VaultContractClient::new(&env, &vault);
+                    let result = vault_client.try_withdraw(&attacker, &1, &attacker, &attacker);
+                    env.storage().instance().set(&DataKey::ReentryFailed, &result.is_err());
+                }
+            }
+        }
+    }
+}
+
+mod fee_token {
+    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Balance(Address),
+        FeeBps,
+    }
+
+    // Burns `fee_bps` of every transfer, so recipients receive less than was sent
+    #[contract]
+    pub struct FeeOnTransferToken;
+
+    #[contractimpl]
+    impl FeeOnTransferToken {
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
+
+        pub fn set_fee_bps(env: Env, fee_bps: i128) {
+            env.storage().instance().set(&DataKey::FeeBps, &fee_bps);
+        }
+
+        pub fn balance(env: Env, account: Address) -> i128 {
+            env.storage().instance().get(&DataKey::Balance(account)).unwrap_or(0)
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&DataKey::Balance(to), &(balance + amount));
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+
+            let fee_bps: i128 = env.storage().instance().get(&DataKey::FeeBps).unwrap_or(0);
+            let fee = amount * fee_bps / 10_000;
+
+            let from_balance = Self::balance(env.clone(), from.clone());
+            env.storage().instance().set(&DataKey::Balance(from), &(from_balance - amount));
+            let to_balance = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&DataKey::Balance(to), &(to_balance + amount - fee));
+        }
+    }
+}
+
+mod counting_token {
+    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Balance(Address),
+        BalanceCalls,
+    }
+
+    // Counts `balance` calls so tests can bound cross-contract reads per vault call
+    #[contract]
+    pub struct CountingToken;
+
+    #[contractimpl]
+    impl CountingToken {
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
+
+        pub fn balance_calls(env: Env) -> u32 {
+            env.storage().instance().get(&DataKey::BalanceCalls).unwrap_or(0)
+        }
+
+        pub fn reset(env: Env) {
+            env.storage().instance().set(&DataKey::BalanceCalls, &0u32);
+        }
+
+        pub fn balance(env: Env, account: Address) -> i128 {
+            let calls = Self::balance_calls(env.clone());
+            env.storage().instance().set(&DataKey::BalanceCalls, &(calls + 1));
+            Self::read_balance(&env, &account)
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::read_balance(&env, &to);
+            env.storage().instance().set(&DataKey::Balance(to), &(balance + amount));
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+
+            let from_balance = Self::read_balance(&env, &from);
+            env.storage().instance().set(&DataKey::Balance(from), &(from_balance - amount));
+            let to_balance = Self::read_balance(&env, &to);
+            env.storage().instance().set(&DataKey::Balance(to), &(to_balance + amount));
+        }
+    }
+
+    impl CountingToken {
+        fn read_balance(env: &Env, account: &Address) -> i128 {
+            env.storage().instance().get(&DataKey::Balance(account.clone())).unwrap_or(0)
+        }
+    }
+}
+
+mod logging_token {
+    use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Balance(Address),
+    }
+
+    // Publishes an event on every transfer so tests can see where it falls relative to
+    // the vault's own mint/burn events. The host forbids re-entering the vault, so the
+    // token can't query `total_supply` directly; event order stands in for it.
+    #[contract]
+    pub struct LoggingToken;
+
+    #[contractimpl]
+    impl LoggingToken {
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
+
+        pub fn balance(env: Env, account: Address) -> i128 {
+            Self::read_balance(&env, &account)
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::read_balance(&env, &to);
+            env.storage().instance().set(&DataKey::Balance(to), &(balance + amount));
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+
+            let from_balance = Self::read_balance(&env, &from);
+            env.storage().instance().set(&DataKey::Balance(from.clone()), &(from_balance - amount));
+            let to_balance = Self::read_balance(&env, &to);
+            env.storage().instance().set(&DataKey::Balance(to.clone()), &(to_balance + amount));
+            env.events().publish((symbol_short!("xfer"), from, to), amount);
+        }
+    }
+
+    impl LoggingToken {
+        fn read_balance(env: &Env, account: &Address) -> i128 {
+            env.storage().instance().get(&DataKey::Balance(account.clone())).unwrap_or(0)
+        }
+    }
+}
+
+mod strategy {
+    use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Asset,
+        Vault,
+        Shortfall,
+    }
+
+    // Holds whatever the vault sends it; tests grow its balance by minting to it directly.
+    // A shortfall makes it send back that much less than asked.
+    #[contract]
+    pub struct MockStrategy;
+
+    #[contractimpl]
+    impl MockStrategy {
+        pub fn initialize(env: Env, asset: Address, vault: Address) {
+            env.storage().instance().set(&DataKey::Asset, &asset);
+            env.storage().instance().set(&DataKey::Vault, &vault);
+        }
+
+        pub fn deposit(_env: Env, _amount: i128) {}
+
+        pub fn set_shortfall(env: Env, shortfall: i128) {
+            env.storage().instance().set(&DataKey::Shortfall, &shortfall);
+        }
+
+        pub fn withdraw(env: Env, amount: i128) {
+            let vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
+            vault.require_auth();
+            let shortfall: i128 = env.storage().instance().get(&DataKey::Shortfall).unwrap_or(0);
+            Self::asset_client(&env).transfer(&env.current_contract_address(), &vault, &(amount - shortfall));
+        }
+
+        pub fn report(env: Env) -> i128 {
+            Self::asset_client(&env).balance(&env.current_contract_address())
+        }
+    }
+
+    impl MockStrategy {
+        fn asset_client(env: &Env) -> token::Client<'_> {
+            let asset: Address = env.storage().instance().get(&DataKey::Asset).unwrap();
+            token::Client::new(env, &asset)
+        }
+    }
+}
+
+mod flash_borrower {
+    use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Bytes, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Vault,
+        Repay,
+    }
+
+    // Sends the loan plus fee back to the vault, or only the loan when `repay` is false
+    #[contract]
+    pub struct MockBorrower;
+
+    #[contractimpl]
+    impl MockBorrower {
+        pub fn initialize(env: Env, vault: Address, repay: bool) {
+            env.storage().instance().set(&DataKey::Vault, &vault);
+            env.storage().instance().set(&DataKey::Repay, &repay);
+        }
+
+        pub fn on_flash_loan(env: Env, asset: Address, amount: i128, fee: i128, _data: Bytes) {
+            let vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
+            let repay: bool = env.storage().instance().get(&DataKey::Repay).unwrap();
+            let owed = if repay { amount + fee } else { amount };
+            token::Client::new(&env, &asset).transfer(&env.current_contract_address(), &vault, &owed);
+        }
+    }
+}
+
+mod oracle {
+    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+    #[contracttype]
+    pub enum DataKey {
+        Price(Address),
+    }
+
+    #[contract]
+    pub struct MockOracle;
+
+    #[contractimpl]
+    impl MockOracle {
+        pub fn set_price(env: Env, asset: Address, price: i128) {
+            env.storage().instance().set(&DataKey::Price(asset), &price);
+        }
+
+        pub fn price(env: Env, asset: Address) -> i128 {
+            env.storage().instance().get(&DataKey::Price(asset)).unwrap()
+        }
+
+        pub fn decimals(_env: Env) -> u32 {
+            7
+        }
     }
 }
 
@@ -67,6 +429,7 @@ struct TestSetup {
     env: Env,
     vault_id: Address,
     token_id: Address,
+    admin: Address,
     user: Address,
     user2: Address,
 }
@@ -74,8 +437,9 @@ struct TestSetup {
 impl TestSetup {
     fn new() -> Self {
         let env = Env::default();
-        let vault_id = env.register_contract(None, VaultContract);
-        let token_id = env.register_contract(None, token::MockToken);
+        let vault_id = env.register(VaultContract, ());
+        let token_id = env.register(token::MockToken, ());
+        let admin = Address::generate(&env);
         let user = Address::generate(&env);
         let user2 = Address::generate(&env);
 
@@ -83,18 +447,26 @@ impl TestSetup {
             env,
             vault_id,
             token_id,
+            admin,
             user,
             user2,
         }
     }
 
     fn initialize_vault(&self, name: &str, symbol: &str, decimals: u32) {
+        self.initialize_vault_with_offset(name, symbol, decimals, 0);
+    }
+
+    fn initialize_vault_with_offset(&self, name: &str, symbol: &str, decimals: u32, decimals_offset: u32) {
         let client = VaultContractClient::new(&self.env, &self.vault_id);
         client.initialize(
+            &self.admin,
             &self.token_id,
             &String::from_str(&self.env, name),
             &String::from_str(&self.env, symbol),
             &decimals,
+            &decimals_offset,
+            &None,
         );
     }
 
@@ -109,6 +481,43 @@ impl TestSetup {
     }
 }
 
+// Smallest module the host accepts: a wasm header plus the `contractenvmetav0` section
+// declaring protocol 22.
+fn minimal_wasm(env: &Env) -> Bytes {
+    let mut wasm = std::vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let name = b"contractenvmetav0";
+    let meta = [0u8, 0, 0, 0, 0, 0, 0, 22, 0, 0, 0, 0];
+    wasm.push(0x00);
+    wasm.push((1 + name.len() + meta.len()) as u8);
+    wasm.push(name.len() as u8);
+    wasm.extend_from_slice(name);
+    wasm.extend_from_slice(&meta);
+    Bytes::from_slice(env, &wasm)
+}
+
+// An account address backed by a known Ed25519 key, for signing permits
+fn permit_signer(env: &Env, seed: u8) -> (SigningKey, Address) {
+    let signing_key = SigningKey::from_bytes(&[seed; 32]);
+    let strkey = stellar_strkey::ed25519::PublicKey(signing_key.verifying_key().to_bytes()).to_string();
+    (signing_key, Address::from_str(env, &strkey))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sign_permit(
+    env: &Env,
+    signing_key: &SigningKey,
+    vault: &Address,
+    owner: &Address,
+    spender: &Address,
+    amount: i128,
+    expiration_ledger: u32,
+    nonce: u64,
+) -> BytesN<64> {
+    let message = (vault.clone(), owner.clone(), spender.clone(), amount, expiration_ledger, nonce).to_xdr(env);
+    let message: std::vec::Vec<u8> = message.iter().collect();
+    BytesN::from_array(env, &signing_key.sign(&message).to_bytes())
+}
+
 #[test]
 fn test_initialize() {
     let setup = TestSetup::new();
@@ -121,10 +530,118 @@ fn test_initialize() {
     assert_eq!(client.decimals(), 18);
     assert_eq!(client.total_supply(), 0);
     assert_eq!(client.asset(), setup.token_id);
+    assert_eq!(client.admin(), setup.admin);
+    assert!(client.is_initialized());
+}
+
+#[test]
+fn test_uninitialized_vault_reports_not_initialized() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    assert_eq!(client.try_asset(), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_total_assets(), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_preview_deposit(&100), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_admin(), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_fee_recipient(), Err(Ok(Error::NotInitialized)));
+    assert_eq!(client.try_fee_config(), Err(Ok(Error::NotInitialized)));
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_set_deposit_cap(&100), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_initialize_auto_derives_metadata() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    client.initialize_auto(&setup.admin, &setup.token_id, &7);
+    assert_eq!(client.name(), String::from_str(&setup.env, "vMock Token"));
+    assert_eq!(client.symbol(), String::from_str(&setup.env, "vMOCK"));
+    assert_eq!(client.decimals(), 7);
+    assert_eq!(client.admin(), setup.admin);
+    assert_eq!(client.try_initialize_auto(&setup.admin, &setup.token_id, &7), Err(Ok(Error::AlreadyInitialized)));
+
+    // An asset without name or symbol gets the defaults
+    let bare_asset = setup.env.register(reentrant_token::ReentrantToken, ());
+    let vault_id = setup.env.register(VaultContract, ());
+    let client = VaultContractClient::new(&setup.env, &vault_id);
+    client.initialize_auto(&setup.admin, &bare_asset, &7);
+    assert_eq!(client.name(), String::from_str(&setup.env, "Vault Shares"));
+    assert_eq!(client.symbol(), String::from_str(&setup.env, "vSHARE"));
+}
+
+#[test]
+fn test_initialize_invalid_decimals() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    let result = client.try_initialize(
+        &setup.admin,
+        &setup.token_id,
+        &String::from_str(&setup.env, "Test Vault"),
+        &String::from_str(&setup.env, "TVAULT"),
+        &37,
+        &0,
+        &None,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidDecimals)));
+
+    let result = client.try_initialize(
+        &setup.admin,
+        &setup.token_id,
+        &String::from_str(&setup.env, "Test Vault"),
+        &String::from_str(&setup.env, "TVAULT"),
+        &30,
+        &7,
+        &None,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidDecimals)));
+    assert!(!client.is_initialized());
+}
+
+#[test]
+fn test_initialize_invalid_asset() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    let result = client.try_initialize(
+        &setup.admin,
+        &Address::generate(&setup.env),
+        &String::from_str(&setup.env, "Test Vault"),
+        &String::from_str(&setup.env, "TVAULT"),
+        &18,
+        &0,
+        &None,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidAddress)));
+    assert!(!client.is_initialized());
+}
+
+#[test]
+fn test_decimals_from_asset() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", u32::MAX, 2);
+
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    assert_eq!(client.asset_decimals(), token_client.decimals());
+    assert_eq!(client.decimals(), token_client.decimals() + 2);
 }
 
 #[test]
-#[should_panic(expected = "Error(Contract, #5)")]
+fn test_is_initialized() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    assert!(!client.is_initialized());
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    assert!(client.is_initialized());
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #13)")]
 fn test_initialize_twice() {
     let setup = TestSetup::new();
     setup.initialize_vault("Test Vault", "TVAULT", 18);
@@ -144,23 +661,23 @@ fn test_erc20_functionality() {
     assert_eq!(client.balance_of(&setup.user2), 0);
     
     setup.env.mock_all_auths();
-    let shares = client.deposit(&100, &setup.user);
+    let shares = client.deposit(&setup.user, &100, &setup.user);
     
     assert!(shares > 0);
     assert_eq!(client.balance_of(&setup.user), shares);
     assert_eq!(client.total_supply(), shares);
     
     setup.env.mock_all_auths();
-    assert!(client.transfer(&setup.user, &setup.user2, &50));
+    client.transfer(&setup.user, &setup.user2, &50);
     assert_eq!(client.balance_of(&setup.user), shares - 50);
     assert_eq!(client.balance_of(&setup.user2), 50);
     
     setup.env.mock_all_auths();
-    assert!(client.approve(&setup.user, &setup.user2, &25));
+    client.approve(&setup.user, &setup.user2, &25, &u32::MAX);
     assert_eq!(client.allowance(&setup.user, &setup.user2), 25);
     
     setup.env.mock_all_auths();
-    assert!(client.transfer_from(&setup.user2, &setup.user, &setup.user2, &25));
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &25);
     assert_eq!(client.balance_of(&setup.user), shares - 75);
     assert_eq!(client.balance_of(&setup.user2), 75);
     assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
@@ -177,13 +694,13 @@ fn test_vault_deposit() {
     
     setup.env.mock_all_auths();
     
-    let shares = client.deposit(&100, &setup.user);
+    let shares = client.deposit(&setup.user, &100, &setup.user);
     assert_eq!(shares, 100);
     assert_eq!(client.balance_of(&setup.user), 100);
     assert_eq!(client.total_supply(), 100);
     assert_eq!(client.total_assets(), 100);
     
-    let shares2 = client.deposit(&50, &setup.user);
+    let shares2 = client.deposit(&setup.user, &50, &setup.user);
     assert_eq!(shares2, 50);
     assert_eq!(client.balance_of(&setup.user), 150);
     assert_eq!(client.total_supply(), 150);
@@ -201,7 +718,7 @@ fn test_vault_mint() {
     
     setup.env.mock_all_auths();
     
-    let assets = client.mint(&100, &setup.user);
+    let assets = client.mint(&setup.user, &100, &setup.user);
     assert_eq!(assets, 100); // 1:1 ratio initially
     assert_eq!(client.balance_of(&setup.user), 100);
     assert_eq!(client.total_supply(), 100);
@@ -219,9 +736,9 @@ fn test_vault_withdraw() {
     
     setup.env.mock_all_auths();
     
-    client.deposit(&200, &setup.user);
+    client.deposit(&setup.user, &200, &setup.user);
     
-    let shares_burned = client.withdraw(&50, &setup.user2, &setup.user);
+    let shares_burned = client.withdraw(&setup.user, &50, &setup.user2, &setup.user);
     assert_eq!(shares_burned, 50); // 1:1 ratio
     assert_eq!(client.balance_of(&setup.user), 150);
     assert_eq!(client.total_supply(), 150);
@@ -239,9 +756,9 @@ fn test_vault_redeem() {
     
     setup.env.mock_all_auths();
     
-    client.deposit(&200, &setup.user);
+    client.deposit(&setup.user, &200, &setup.user);
     
-    let assets_received = client.redeem(&50, &setup.user2, &setup.user);
+    let assets_received = client.redeem(&setup.user, &50, &setup.user2, &setup.user);
     assert_eq!(assets_received, 50); // 1:1 ratio
     assert_eq!(client.balance_of(&setup.user), 150);
     assert_eq!(client.total_supply(), 150);
@@ -262,7 +779,7 @@ fn test_conversion_functions() {
     assert_eq!(client.convert_to_shares(&100), 100);
     assert_eq!(client.convert_to_assets(&100), 100);
     
-    client.deposit(&200, &setup.user);
+    client.deposit(&setup.user, &200, &setup.user);
     assert_eq!(client.convert_to_shares(&100), 100);
     assert_eq!(client.convert_to_assets(&100), 100);
 }
@@ -283,7 +800,7 @@ fn test_preview_functions() {
     assert_eq!(client.preview_withdraw(&100), 100);
     assert_eq!(client.preview_redeem(&100), 100);
     
-    client.deposit(&200, &setup.user);
+    client.deposit(&setup.user, &200, &setup.user);
     assert_eq!(client.preview_deposit(&100), 100);
     assert_eq!(client.preview_mint(&100), 100);
     assert_eq!(client.preview_withdraw(&100), 100);
@@ -306,7 +823,7 @@ fn test_max_functions() {
     assert_eq!(client.max_withdraw(&setup.user), 0); // No shares yet
     assert_eq!(client.max_redeem(&setup.user), 0); // No shares yet
     
-    client.deposit(&200, &setup.user);
+    client.deposit(&setup.user, &200, &setup.user);
     assert_eq!(client.max_withdraw(&setup.user), 200);
     assert_eq!(client.max_redeem(&setup.user), 200);
 }
@@ -322,7 +839,7 @@ fn test_deposit_zero_assets() {
     setup.mint_tokens(&setup.user, 1000);
     
     setup.env.mock_all_auths();
-    client.deposit(&0, &setup.user);
+    client.deposit(&setup.user, &0, &setup.user);
 }
 
 #[test]
@@ -336,7 +853,7 @@ fn test_mint_zero_shares() {
     setup.mint_tokens(&setup.user, 1000);
     
     setup.env.mock_all_auths();
-    client.mint(&0, &setup.user);
+    client.mint(&setup.user, &0, &setup.user);
 }
 
 #[test]
@@ -351,7 +868,7 @@ fn test_transfer_insufficient_balance() {
     
     setup.env.mock_all_auths();
     
-    client.deposit(&100, &setup.user);
+    client.deposit(&setup.user, &100, &setup.user);
     
     client.transfer(&setup.user, &setup.user2, &200);
 }
@@ -368,9 +885,9 @@ fn test_transfer_from_insufficient_allowance() {
     
     setup.env.mock_all_auths();
     
-    client.deposit(&100, &setup.user);
+    client.deposit(&setup.user, &100, &setup.user);
     
-    client.approve(&setup.user, &setup.user2, &50);
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
     
     client.transfer_from(&setup.user2, &setup.user, &setup.user2, &100);
 }
@@ -386,13 +903,4632 @@ fn test_events() {
     
     setup.env.mock_all_auths();
     
-    client.deposit(&100, &setup.user);
+    client.deposit(&setup.user, &100, &setup.user);
     
     let events = setup.env.events().all();
-    assert!(events.len() > 0);
+    assert!(!events.is_empty());
     
     client.transfer(&setup.user, &setup.user2, &50);
     
+    // `events().all()` only reports events from the last invocation
     let events = setup.env.events().all();
-    assert!(events.len() > 1);
-}
\ No newline at end of file
+    assert_eq!(events.len(), 1);
+}
+
+#[test]
+fn test_events_lead_with_version_topic() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let assert_versioned = |env: &Env, expected: &[&str]| {
+        let events = env.events().all();
+        assert_eq!(events.len() as usize, expected.len());
+        for ((contract, topics, _), name) in events.iter().zip(expected.iter()) {
+            assert_eq!(contract, setup.vault_id);
+            let version: Symbol = topics.get(0).unwrap().into_val(env);
+            let topic: Symbol = topics.get(1).unwrap().into_val(env);
+            assert_eq!(version, symbol_short!("v1"));
+            assert_eq!(topic, Symbol::new(env, name));
+        }
+    };
+
+    client.deposit(&setup.user, &100, &setup.user);
+    assert_versioned(&setup.env, &["mint", "deposit"]);
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
+    assert_versioned(&setup.env, &["approve"]);
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &50);
+    assert_versioned(&setup.env, &["approve", "transfer"]);
+    client.redeem(&setup.user2, &50, &setup.user2, &setup.user2);
+    assert_versioned(&setup.env, &["burn", "withdraw"]);
+    client.pause(&setup.admin);
+    assert_versioned(&setup.env, &["pause"]);
+}
+
+#[test]
+fn test_deposit_requires_caller_auth() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.deposit(&setup.user, &100, &setup.user2);
+    assert_eq!(
+        setup.env.auths(),
+        std::vec![(
+            setup.user.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    setup.vault_id.clone(),
+                    Symbol::new(&setup.env, "deposit"),
+                    (setup.user.clone(), 100i128, setup.user2.clone()).into_val(&setup.env),
+                )),
+                sub_invocations: std::vec![AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        setup.token_id.clone(),
+                        Symbol::new(&setup.env, "transfer"),
+                        (setup.user.clone(), setup.vault_id.clone(), 100i128).into_val(&setup.env),
+                    )),
+                    sub_invocations: std::vec![],
+                }],
+            }
+        )]
+    );
+
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    assert_eq!(token_client.balance(&setup.user), 900);
+    assert_eq!(token_client.balance(&setup.vault_id), 100);
+    assert_eq!(client.balance_of(&setup.user2), 100);
+}
+
+#[test]
+fn test_mint_requires_caller_auth() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.mint(&setup.user, &100, &setup.user);
+    let auths = setup.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, setup.user);
+}
+
+#[test]
+#[should_panic]
+fn test_deposit_without_auth() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    client.deposit(&setup.user, &100, &setup.user);
+}
+
+#[test]
+fn test_conversion_overflow() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    let large = i128::MAX / 4;
+    setup.mint_tokens(&setup.user, large);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &large, &setup.user);
+
+    let limit = i128::MAX / (client.total_supply() + 1);
+    assert_eq!(client.convert_to_shares(&limit), limit);
+    assert_eq!(client.convert_to_assets(&limit), limit);
+
+    assert_eq!(client.try_convert_to_shares(&(limit + 1)), Err(Ok(Error::Overflow)));
+    assert_eq!(client.try_convert_to_assets(&(limit + 1)), Err(Ok(Error::Overflow)));
+    assert_eq!(client.try_preview_mint(&(limit + 1)), Err(Ok(Error::Overflow)));
+    assert_eq!(client.try_preview_withdraw(&(limit + 1)), Err(Ok(Error::Overflow)));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #6)")]
+fn test_deposit_overflow() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    let large = i128::MAX / 4;
+    setup.mint_tokens(&setup.user, large);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &large, &setup.user);
+    client.deposit(&setup.user2, &1000, &setup.user2);
+}
+
+#[test]
+fn test_decimals_offset() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", 7, 3);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    assert_eq!(client.decimals(), 10);
+    assert_eq!(client.decimals_offset(), 3);
+
+    setup.env.mock_all_auths();
+    let shares = client.deposit(&setup.user, &100, &setup.user);
+    assert_eq!(shares, 100_000);
+    assert_eq!(client.convert_to_assets(&shares), 100);
+}
+
+#[test]
+fn test_donation_attack_mitigated() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", 18, 3);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_001);
+    setup.mint_tokens(&setup.user2, 10_000);
+
+    setup.env.mock_all_auths();
+
+    // Attacker takes the first share and donates straight to the vault
+    let attacker_shares = client.deposit(&setup.user, &1, &setup.user);
+    token_client.transfer(&setup.user, &setup.vault_id, &10_000);
+
+    // Victim is not diluted to zero shares and can recover nearly all of the deposit
+    let victim_shares = client.deposit(&setup.user2, &10_000, &setup.user2);
+    assert!(victim_shares > 0);
+    assert!(client.convert_to_assets(&victim_shares) >= 9_990);
+
+    // The donation is mostly captured by the virtual shares, so the attack loses money
+    assert!(client.convert_to_assets(&attacker_shares) < 10_001);
+}
+
+#[test]
+fn test_set_admin() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    client.set_admin(&setup.user);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(setup.env.events().all().len(), 1);
+    assert_eq!(client.admin(), setup.admin);
+    assert_eq!(client.pending_admin(), Some(setup.user.clone()));
+
+    client.accept_admin();
+    assert_eq!(setup.env.auths()[0].0, setup.user);
+    assert_eq!(client.admin(), setup.user);
+}
+
+#[test]
+fn test_set_admin_unauthorized() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let result = client
+        .mock_auths(&[MockAuth {
+            address: &setup.user,
+            invoke: &MockAuthInvoke {
+                contract: &setup.vault_id,
+                fn_name: "set_admin",
+                args: (setup.user.clone(),).into_val(&setup.env),
+                sub_invokes: &[],
+            },
+        }])
+        .try_set_admin(&setup.user);
+    assert!(result.is_err());
+    assert_eq!(client.admin(), setup.admin);
+    assert_eq!(client.pending_admin(), None);
+}
+
+#[test]
+fn test_two_step_admin_transfer() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_accept_admin(), Err(Ok(Error::NoPendingAdmin)));
+
+    client.propose_admin(&setup.user);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(client.pending_admin(), Some(setup.user.clone()));
+    assert_eq!(client.admin(), setup.admin);
+
+    client.accept_admin();
+    assert_eq!(setup.env.auths()[0].0, setup.user);
+    assert_eq!(client.admin(), setup.user);
+    assert_eq!(client.pending_admin(), None);
+}
+
+#[test]
+fn test_cancel_admin_transfer() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_cancel_admin_transfer(), Err(Ok(Error::NoPendingAdmin)));
+
+    client.propose_admin(&setup.user);
+    client.cancel_admin_transfer();
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(client.pending_admin(), None);
+    assert_eq!(client.try_accept_admin(), Err(Ok(Error::NoPendingAdmin)));
+    assert_eq!(client.admin(), setup.admin);
+}
+
+#[test]
+fn test_accept_admin_unauthorized() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    client.propose_admin(&setup.user);
+
+    // Only the proposed admin can accept
+    let result = client
+        .mock_auths(&[MockAuth {
+            address: &setup.user2,
+            invoke: &MockAuthInvoke {
+                contract: &setup.vault_id,
+                fn_name: "accept_admin",
+                args: ().into_val(&setup.env),
+                sub_invokes: &[],
+            },
+        }])
+        .try_accept_admin();
+    assert!(result.is_err());
+    assert_eq!(client.admin(), setup.admin);
+    assert_eq!(client.pending_admin(), Some(setup.user.clone()));
+}
+
+#[test]
+fn test_set_metadata() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    let name = String::from_str(&setup.env, "Renamed Vault");
+    let symbol = String::from_str(&setup.env, "RVAULT");
+    client.set_metadata(&name, &symbol);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(
+        setup.env.events().all(),
+        vec![
+            &setup.env,
+            (
+                setup.vault_id.clone(),
+                (symbol_short!("v1"), Symbol::new(&setup.env, "metadata_updated"), setup.admin.clone()).into_val(&setup.env),
+                (name.clone(), symbol.clone()).into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(client.name(), name);
+    assert_eq!(client.symbol(), symbol);
+    assert_eq!(client.decimals(), 18);
+}
+
+#[test]
+fn test_set_metadata_unauthorized() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let name = String::from_str(&setup.env, "Renamed Vault");
+    let symbol = String::from_str(&setup.env, "RVAULT");
+    let result = client
+        .mock_auths(&[MockAuth {
+            address: &setup.user,
+            invoke: &MockAuthInvoke {
+                contract: &setup.vault_id,
+                fn_name: "set_metadata",
+                args: (name.clone(), symbol.clone()).into_val(&setup.env),
+                sub_invokes: &[],
+            },
+        }])
+        .try_set_metadata(&name, &symbol);
+    assert!(result.is_err());
+    assert_eq!(client.name(), String::from_str(&setup.env, "Test Vault"));
+    assert_eq!(client.symbol(), String::from_str(&setup.env, "TVAULT"));
+}
+
+#[test]
+fn test_pause_blocks_deposits() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.pause(&setup.admin);
+    assert!(client.paused());
+    assert_eq!(client.try_deposit(&setup.user, &100, &setup.user), Err(Ok(Error::Paused)));
+    assert_eq!(client.try_mint(&setup.user, &100, &setup.user), Err(Ok(Error::Paused)));
+
+    client.unpause(&setup.admin);
+    assert!(!client.paused());
+    assert_eq!(client.deposit(&setup.user, &100, &setup.user), 100);
+    assert_eq!(client.mint(&setup.user, &100, &setup.user), 100);
+}
+
+#[test]
+fn test_pause_allows_exits() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.deposit(&setup.user, &200, &setup.user);
+    client.pause(&setup.admin);
+
+    assert_eq!(client.withdraw(&setup.user, &50, &setup.user, &setup.user), 50);
+    assert_eq!(client.redeem(&setup.user, &50, &setup.user, &setup.user), 50);
+    assert_eq!(client.balance_of(&setup.user), 100);
+}
+
+#[test]
+fn test_outflow_under_limit_allowed() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.set_max_outflow_per_ledger(&2_000);
+
+    // Exactly 20% of 1000 in one ledger is allowed
+    client.withdraw(&setup.user, &150, &setup.user, &setup.user);
+    client.redeem(&setup.user, &50, &setup.user, &setup.user);
+    assert!(!client.paused());
+    assert!(!client.breaker_tripped());
+
+    // The count starts over each ledger
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.withdraw(&setup.user, &150, &setup.user, &setup.user);
+    assert!(!client.paused());
+}
+
+#[test]
+fn test_outflow_over_limit_auto_pauses() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.set_max_outflow_per_ledger(&2_000);
+
+    client.withdraw(&setup.user, &150, &setup.user, &setup.user);
+    // Crossing the limit completes the withdrawal and trips the breaker
+    client.withdraw(&setup.user, &51, &setup.user, &setup.user);
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        (symbol_short!("v1"), Symbol::new(&setup.env, "auto_paused")).into_val(&setup.env),
+        (201i128, 1000i128).into_val(&setup.env),
+    )));
+    assert!(client.paused());
+    assert!(client.breaker_tripped());
+    assert_eq!(client.balance_of(&setup.user), 799);
+
+    // Unlike a manual pause, exits are blocked too
+    assert_eq!(client.try_withdraw(&setup.user, &1, &setup.user, &setup.user), Err(Ok(Error::Paused)));
+    assert_eq!(client.try_redeem(&setup.user, &1, &setup.user, &setup.user), Err(Ok(Error::Paused)));
+    assert_eq!(client.try_deposit(&setup.user, &1, &setup.user), Err(Ok(Error::Paused)));
+
+    client.unpause(&setup.admin);
+    assert!(!client.breaker_tripped());
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.withdraw(&setup.user, &1, &setup.user, &setup.user);
+}
+
+#[test]
+fn test_breaker_covers_queued_withdrawals() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.set_max_outflow_per_ledger(&2_000);
+
+    let first = client.request_withdraw(&setup.user, &500, &setup.user2, &setup.user);
+    let second = client.request_withdraw(&setup.user, &100, &setup.user2, &setup.user);
+    assert!(!client.breaker_tripped());
+
+    // Paying out 500 of the 1000 held crosses the limit
+    assert_eq!(client.fulfill_withdraw(&first), 500);
+    assert!(client.paused());
+    assert!(client.breaker_tripped());
+
+    assert_eq!(client.try_fulfill_withdraw(&second), Err(Ok(Error::Paused)));
+    assert_eq!(
+        client.try_request_withdraw(&setup.user, &100, &setup.user2, &setup.user),
+        Err(Ok(Error::Paused))
+    );
+
+    client.unpause(&setup.admin);
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    assert_eq!(client.fulfill_withdraw(&second), 100);
+    assert_eq!(token_client.balance(&setup.user2), 600);
+    assert!(!client.breaker_tripped());
+}
+
+#[test]
+fn test_shutdown_is_redeem_only() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &600, &setup.user);
+    client.deposit(&setup.user2, &400, &setup.user2);
+    client.set_exit_fee(&setup.admin, &500);
+
+    client.shutdown();
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert!(client.is_shutdown());
+    assert_eq!(client.try_deposit(&setup.user, &100, &setup.user), Err(Ok(Error::Shutdown)));
+    assert_eq!(client.try_mint(&setup.user, &100, &setup.user), Err(Ok(Error::Shutdown)));
+    assert_eq!(client.try_withdraw(&setup.user, &100, &setup.user, &setup.user), Err(Ok(Error::Shutdown)));
+    assert_eq!(client.max_deposit(&setup.user), 0);
+    assert_eq!(client.max_mint(&setup.user), 0);
+
+    // Pro-rata with the exit fee waived
+    assert_eq!(client.preview_redeem(&600), 600);
+    assert_eq!(client.redeem(&setup.user, &600, &setup.user, &setup.user), 600);
+    assert_eq!(token_client.balance(&setup.user), 1000);
+    assert_eq!(client.fees_collected(), (0, 0, 0));
+
+    // Unpausing doesn't reopen the vault
+    client.unpause(&setup.admin);
+    assert!(client.is_shutdown());
+    assert_eq!(client.try_deposit(&setup.user2, &100, &setup.user2), Err(Ok(Error::Shutdown)));
+    assert_eq!(client.redeem(&setup.user2, &400, &setup.user2, &setup.user2), 400);
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+#[should_panic]
+fn test_pause_requires_admin() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    client.pause(&setup.admin);
+}
+
+#[test]
+fn test_pauser_role() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let pauser = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_pause(&pauser), Err(Ok(Error::Unauthorized)));
+
+    client.grant_role(&PAUSER, &pauser);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert!(client.has_role(&PAUSER, &pauser));
+    assert!(!client.has_role(&FEE_MANAGER, &pauser));
+
+    client.pause(&pauser);
+    assert_eq!(setup.env.auths()[0].0, pauser);
+    assert!(client.paused());
+    client.unpause(&pauser);
+    assert!(!client.paused());
+
+    // The role doesn't extend to fee settings
+    assert_eq!(client.try_set_entry_fee(&pauser, &100), Err(Ok(Error::Unauthorized)));
+    assert_eq!(client.try_set_fee_recipient(&pauser, &pauser), Err(Ok(Error::Unauthorized)));
+    assert_eq!(client.entry_fee_bps(), 0);
+
+    client.revoke_role(&PAUSER, &pauser);
+    assert!(!client.has_role(&PAUSER, &pauser));
+    assert_eq!(client.try_pause(&pauser), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn test_fee_manager_role() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let manager = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    client.grant_role(&FEE_MANAGER, &manager);
+    client.set_entry_fee(&manager, &100);
+    client.set_exit_fee(&manager, &50);
+    assert_eq!(client.entry_fee_bps(), 100);
+    assert_eq!(client.exit_fee_bps(), 50);
+    assert_eq!(client.try_pause(&manager), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn test_entry_fee() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let treasury = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.set_fee_recipient(&setup.admin, &treasury);
+    client.set_entry_fee(&setup.admin, &100);
+
+    // 1% on top of the net assets: 1010 gross pays 10 in fees
+    let preview = client.preview_deposit(&1010);
+    assert_eq!(preview, 1000);
+    assert_eq!(client.deposit(&setup.user, &1010, &setup.user), preview);
+    assert_eq!(token_client.balance(&treasury), 10);
+    assert_eq!(client.total_assets(), 1000);
+
+    let preview = client.preview_mint(&500);
+    assert_eq!(preview, 505);
+    assert_eq!(client.mint(&setup.user, &500, &setup.user), preview);
+    assert_eq!(token_client.balance(&treasury), 15);
+    assert_eq!(client.total_assets(), 1500);
+    assert_eq!(client.balance_of(&setup.user), 1500);
+}
+
+#[test]
+fn test_exit_fee() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let treasury = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &2000, &setup.user);
+    client.set_fee_recipient(&setup.admin, &treasury);
+    client.set_exit_fee(&setup.admin, &200);
+
+    let preview = client.preview_withdraw(&500);
+    assert_eq!(preview, 510);
+    assert_eq!(client.withdraw(&setup.user, &500, &setup.user2, &setup.user), preview);
+    assert_eq!(token_client.balance(&setup.user2), 500);
+    assert_eq!(token_client.balance(&treasury), 10);
+
+    let preview = client.preview_redeem(&510);
+    assert_eq!(preview, 500);
+    assert_eq!(client.redeem(&setup.user, &510, &setup.user2, &setup.user), preview);
+    assert_eq!(token_client.balance(&setup.user2), 1000);
+    assert_eq!(token_client.balance(&treasury), 20);
+    assert_eq!(client.balance_of(&setup.user), 980);
+    assert_eq!(client.total_assets(), 980);
+}
+
+#[test]
+fn test_fee_recipient_defaults_to_admin() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    assert_eq!(client.fee_recipient(), setup.admin);
+    assert_eq!(client.entry_fee_bps(), 0);
+    assert_eq!(client.exit_fee_bps(), 0);
+}
+
+#[test]
+fn test_fee_cap() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_set_entry_fee(&setup.admin, &10_001), Err(Ok(Error::InvalidFee)));
+    assert_eq!(client.try_set_exit_fee(&setup.admin, &10_001), Err(Ok(Error::InvalidFee)));
+    client.set_entry_fee(&setup.admin, &10_000);
+    assert_eq!(client.entry_fee_bps(), 10_000);
+}
+
+#[test]
+fn test_preview_rounding_non_unit_ratio() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    // supply 100, assets 150
+    client.deposit(&setup.user, &100, &setup.user);
+    token_client.transfer(&setup.user, &setup.vault_id, &50);
+    assert_eq!(client.total_supply(), 100);
+    assert_eq!(client.total_assets(), 150);
+
+    // 10 * 101 / 151 = 6.69
+    assert_eq!(client.preview_deposit(&10), 6);
+    assert_eq!(client.preview_withdraw(&10), 7);
+    // 10 * 151 / 101 = 14.95
+    assert_eq!(client.preview_mint(&10), 15);
+    assert_eq!(client.preview_redeem(&10), 14);
+}
+
+#[test]
+fn test_deposit_with_min_shares() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+
+    assert_eq!(client.deposit_with_min_shares(&setup.user, &100, &setup.user, &100), 100);
+
+    // The rate moves against user2 before the deposit lands
+    let expected = client.preview_deposit(&100);
+    token_client.transfer(&setup.user, &setup.vault_id, &100);
+
+    assert_eq!(
+        client.try_deposit_with_min_shares(&setup.user2, &100, &setup.user2, &expected),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(token_client.balance(&setup.user2), 1000);
+    assert_eq!(client.balance_of(&setup.user2), 0);
+
+    let shares = client.deposit_with_min_shares(&setup.user2, &100, &setup.user2, &(expected / 2));
+    assert!(shares < expected);
+}
+
+#[test]
+fn test_redeem_with_min_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.deposit(&setup.user, &200, &setup.user);
+
+    // A fee switched on before the redeem lands lowers the payout
+    let expected = client.preview_redeem(&100);
+    client.set_exit_fee(&setup.admin, &1000);
+
+    assert_eq!(
+        client.try_redeem_with_min_assets(&setup.user, &100, &setup.user, &setup.user, &expected),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(client.balance_of(&setup.user), 200);
+    assert_eq!(token_client.balance(&setup.user), 800);
+
+    let assets = client.redeem_with_min_assets(&setup.user, &100, &setup.user, &setup.user, &90);
+    assert!(assets < expected);
+    assert!(assets >= 90);
+}
+
+#[test]
+fn test_deposit_and_redeem_by_deadline() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    setup.env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    assert_eq!(client.try_deposit_by(&setup.user, &200, &setup.user, &99), Err(Ok(Error::DeadlinePassed)));
+    assert_eq!(token_client.balance(&setup.user), 1000);
+    assert_eq!(client.deposit_by(&setup.user, &200, &setup.user, &100), 200);
+    assert_eq!(client.deposit_by(&setup.user, &100, &setup.user, &150), 100);
+
+    assert_eq!(
+        client.try_redeem_by(&setup.user, &100, &setup.user, &setup.user, &99),
+        Err(Ok(Error::DeadlinePassed))
+    );
+    assert_eq!(client.balance_of(&setup.user), 300);
+    assert_eq!(client.redeem_by(&setup.user, &100, &setup.user, &setup.user, &150), 100);
+    assert_eq!(client.balance_of(&setup.user), 200);
+}
+
+#[test]
+fn test_structured_vault_events() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    client.deposit(&setup.user, &100, &setup.user2);
+    let (contract, topics, data) = setup.env.events().all().last().unwrap();
+    assert_eq!(contract, setup.vault_id);
+    assert_eq!(
+        topics,
+        (symbol_short!("v1"), symbol_short!("deposit"), setup.user.clone(), setup.user2.clone()).into_val(&setup.env)
+    );
+    let event: DepositEvent = data.into_val(&setup.env);
+    assert_eq!(
+        event,
+        DepositEvent {
+            sender: setup.user.clone(),
+            owner: setup.user2.clone(),
+            assets: 100,
+            shares: 100,
+        }
+    );
+
+    client.redeem(&setup.user2, &40, &setup.user, &setup.user2);
+    let (_, topics, data) = setup.env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("v1"), symbol_short!("withdraw"), setup.user2.clone(), setup.user2.clone()).into_val(&setup.env)
+    );
+    let event: WithdrawEvent = data.into_val(&setup.env);
+    assert_eq!(
+        event,
+        WithdrawEvent {
+            sender: setup.user2.clone(),
+            receiver: setup.user.clone(),
+            owner: setup.user2.clone(),
+            assets: 40,
+            shares: 40,
+        }
+    );
+}
+
+#[test]
+fn test_increase_decrease_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+
+    assert!(client.increase_allowance(&setup.user, &setup.user2, &30));
+    let amount: i128 = setup.env.events().all().last().unwrap().2.into_val(&setup.env);
+    assert_eq!(amount, 30);
+    assert!(client.increase_allowance(&setup.user, &setup.user2, &20));
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 50);
+
+    assert!(client.decrease_allowance(&setup.user, &setup.user2, &15));
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 35);
+
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &10);
+    assert!(client.decrease_allowance(&setup.user, &setup.user2, &25));
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+    assert_eq!(client.balance_of(&setup.user2), 10);
+}
+
+#[test]
+fn test_transfer_checked() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+
+    assert!(client.transfer_checked(&setup.user, &setup.user2, &60, &40));
+    assert_eq!(client.balance_of(&setup.user), 40);
+    assert_eq!(client.balance_of(&setup.user2), 60);
+
+    assert_eq!(
+        client.try_transfer_checked(&setup.user, &setup.user2, &1, &40),
+        Err(Ok(Error::WouldViolateMinimum))
+    );
+    assert_eq!(client.balance_of(&setup.user), 40);
+    assert_eq!(client.balance_of(&setup.user2), 60);
+}
+
+#[test]
+fn test_self_transfer_rejected() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
+
+    assert_eq!(
+        client.try_transfer(&setup.user, &setup.user, &10),
+        Err(Ok(Error::SelfTransfer))
+    );
+    assert_eq!(
+        client.try_transfer_from(&setup.user2, &setup.user, &setup.user, &10),
+        Err(Ok(Error::SelfTransfer))
+    );
+    assert_eq!(
+        client.try_transfer(&setup.user, &setup.vault_id, &10),
+        Err(Ok(Error::InvalidAddress))
+    );
+
+    assert_eq!(client.balance_of(&setup.user), 100);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 50);
+}
+
+#[test]
+fn test_allowance_events_on_spend() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
+
+    let approve_topics: Vec<soroban_sdk::Val> =
+        (symbol_short!("v1"), symbol_short!("approve"), setup.user.clone(), setup.user2.clone()).into_val(&setup.env);
+    let last_allowance = |env: &Env| -> i128 {
+        let (_, _, data) = env
+            .events()
+            .all()
+            .iter()
+            .find(|(_, topics, _)| *topics == approve_topics)
+            .unwrap();
+        data.into_val(env)
+    };
+
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &20);
+    assert_eq!(last_allowance(&setup.env), 30);
+
+    client.redeem(&setup.user2, &10, &setup.user2, &setup.user);
+    assert_eq!(last_allowance(&setup.env), 20);
+
+    client.withdraw(&setup.user2, &5, &setup.user2, &setup.user);
+    assert_eq!(last_allowance(&setup.env), 15);
+}
+
+#[test]
+fn test_infinite_allowance_is_never_reduced() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert!(!client.is_infinite_allowance(&setup.user, &setup.user2));
+
+    client.approve(&setup.user, &setup.user2, &i128::MAX, &u32::MAX);
+    assert_eq!(
+        setup.env.events().all(),
+        vec![
+            &setup.env,
+            (
+                setup.vault_id.clone(),
+                (symbol_short!("v1"), Symbol::new(&setup.env, "approve_infinite"), setup.user.clone(), setup.user2.clone())
+                    .into_val(&setup.env),
+                u32::MAX.into_val(&setup.env),
+            ),
+        ]
+    );
+    assert!(client.is_infinite_allowance(&setup.user, &setup.user2));
+
+    // No spend rewrites the allowance or publishes an approval
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &100);
+    assert_eq!(setup.env.events().all().len(), 1);
+    client.withdraw(&setup.user2, &100, &setup.user2, &setup.user);
+    client.redeem(&setup.user2, &100, &setup.user2, &setup.user);
+    client.burn_from(&setup.user2, &setup.user, &100);
+    client.request_withdraw(&setup.user2, &100, &setup.user2, &setup.user);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), i128::MAX);
+    assert!(client.is_infinite_allowance(&setup.user, &setup.user2));
+    assert_eq!(client.balance_of(&setup.user), 500);
+
+    // A finite approval replaces it and is spent down as usual
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
+    assert!(!client.is_infinite_allowance(&setup.user, &setup.user2));
+    client.redeem(&setup.user2, &20, &setup.user2, &setup.user);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 30);
+}
+
+#[test]
+fn test_decrease_allowance_below_zero() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    client.approve(&setup.user, &setup.user2, &10, &u32::MAX);
+
+    assert_eq!(
+        client.try_decrease_allowance(&setup.user, &setup.user2, &11),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 10);
+
+    client.approve(&setup.user, &setup.user2, &i128::MAX, &u32::MAX);
+    assert_eq!(
+        client.try_increase_allowance(&setup.user, &setup.user2, &1),
+        Err(Ok(Error::Overflow))
+    );
+}
+
+#[test]
+fn test_allowance_expiry() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+
+    let expiration_ledger = setup.env.ledger().sequence() + 10;
+    client.approve_with_expiry(&setup.user, &setup.user2, &50, &expiration_ledger);
+
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &20);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 30);
+
+    setup.env.ledger().set_sequence_number(expiration_ledger);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 30);
+
+    setup.env.ledger().set_sequence_number(expiration_ledger + 1);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+    assert_eq!(
+        client.try_transfer_from(&setup.user2, &setup.user, &setup.user2, &1),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    assert_eq!(
+        client.try_redeem(&setup.user2, &1, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+}
+
+#[test]
+fn test_approve_never_expires() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    setup.env.mock_all_auths();
+    client.approve(&setup.user, &setup.user2, &50, &u32::MAX);
+
+    setup.env.ledger().set_sequence_number(setup.env.ledger().sequence() + 1000);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 50);
+}
+
+#[test]
+fn test_burn() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+
+    client.burn(&setup.user, &40);
+    assert_eq!(client.balance_of(&setup.user), 60);
+    assert_eq!(client.total_supply(), 60);
+    assert_eq!(client.total_assets(), 100);
+
+    assert_eq!(client.try_burn(&setup.user, &61), Err(Ok(Error::InsufficientBalance)));
+    assert_eq!(client.total_supply(), 60);
+}
+
+#[test]
+fn test_burn_from() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+    client.approve(&setup.user, &setup.user2, &30, &u32::MAX);
+
+    client.burn_from(&setup.user2, &setup.user, &20);
+    assert_eq!(client.balance_of(&setup.user), 80);
+    assert_eq!(client.total_supply(), 80);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 10);
+
+    assert_eq!(
+        client.try_burn_from(&setup.user2, &setup.user, &11),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+}
+
+#[test]
+fn test_deposit_cap() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_deposit_cap(&500);
+    assert_eq!(client.deposit_cap(), 500);
+    assert_eq!(client.max_deposit(&setup.user), 500);
+    assert_eq!(client.max_mint(&setup.user), 500);
+
+    // Near the cap
+    client.deposit(&setup.user, &400, &setup.user);
+    assert_eq!(client.max_deposit(&setup.user), 100);
+    assert_eq!(client.max_mint(&setup.user), 100);
+
+    // Over the cap
+    assert_eq!(
+        client.try_deposit(&setup.user, &101, &setup.user),
+        Err(Ok(Error::DepositCapExceeded))
+    );
+    assert_eq!(
+        client.try_mint(&setup.user, &101, &setup.user),
+        Err(Ok(Error::DepositCapExceeded))
+    );
+
+    // At the cap
+    client.deposit(&setup.user, &100, &setup.user);
+    assert_eq!(client.total_assets(), 500);
+    assert_eq!(client.max_deposit(&setup.user), 0);
+    assert_eq!(client.max_mint(&setup.user), 0);
+    assert_eq!(
+        client.try_deposit(&setup.user, &1, &setup.user),
+        Err(Ok(Error::DepositCapExceeded))
+    );
+}
+
+#[test]
+fn test_mint_over_cap_is_atomic() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &400, &setup.user);
+    token_client.transfer(&setup.user, &setup.vault_id, &100);
+    client.set_deposit_cap(&600);
+
+    // 81 shares is under the 100 assets of headroom, but at 501 / 401 it costs 102 assets
+    assert_eq!(client.preview_mint(&81), 102);
+    assert_eq!(
+        client.try_mint(&setup.user, &81, &setup.user),
+        Err(Ok(Error::DepositCapExceeded))
+    );
+    assert_eq!(client.balance_of(&setup.user), 400);
+    assert_eq!(client.total_supply(), 400);
+    assert_eq!(token_client.balance(&setup.user), 500);
+    assert_eq!(client.total_assets(), 500);
+
+    // The largest mint that fits goes through in full
+    assert_eq!(client.mint(&setup.user, &80, &setup.user), 100);
+    assert_eq!(client.total_assets(), 600);
+    assert_eq!(client.balance_of(&setup.user), 480);
+}
+
+#[test]
+fn test_deposit_cap_below_total_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &400, &setup.user);
+    client.set_deposit_cap(&300);
+    assert_eq!(client.max_deposit(&setup.user), 0);
+}
+
+#[test]
+fn test_user_deposit_cap() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_user_deposit_cap(&300);
+    assert_eq!(client.user_deposit_cap(), 300);
+    assert_eq!(client.max_deposit(&setup.user), 300);
+
+    client.deposit(&setup.user, &200, &setup.user);
+    assert_eq!(client.user_deposited(&setup.user), 200);
+    assert_eq!(client.max_deposit(&setup.user), 100);
+    assert_eq!(client.max_deposit(&setup.user2), 300);
+
+    assert_eq!(
+        client.try_deposit(&setup.user, &101, &setup.user),
+        Err(Ok(Error::UserCapExceeded))
+    );
+    assert_eq!(
+        client.try_mint(&setup.user, &101, &setup.user),
+        Err(Ok(Error::UserCapExceeded))
+    );
+
+    // The cap follows the receiver, not the caller
+    assert_eq!(
+        client.try_deposit(&setup.user2, &101, &setup.user),
+        Err(Ok(Error::UserCapExceeded))
+    );
+    client.deposit(&setup.user, &300, &setup.user2);
+
+    // Withdrawing frees up capacity again
+    client.withdraw(&setup.user, &150, &setup.user, &setup.user);
+    assert_eq!(client.user_deposited(&setup.user), 50);
+    client.deposit(&setup.user, &250, &setup.user);
+    assert_eq!(client.max_deposit(&setup.user), 0);
+}
+
+#[test]
+fn test_user_and_global_deposit_caps() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_user_deposit_cap(&400);
+    client.set_deposit_cap(&600);
+
+    client.deposit(&setup.user, &400, &setup.user);
+
+    // user2 has 400 of their own cap left but only 200 remains globally
+    assert_eq!(client.max_deposit(&setup.user2), 200);
+    assert_eq!(
+        client.try_deposit(&setup.user2, &201, &setup.user2),
+        Err(Ok(Error::DepositCapExceeded))
+    );
+    client.deposit(&setup.user2, &200, &setup.user2);
+
+    // A repeated receiver in a batch counts against a single cap
+    client.set_deposit_cap(&2000);
+    let entries = vec![&setup.env, (100i128, setup.user2.clone()), (150i128, setup.user2.clone())];
+    assert_eq!(
+        client.try_batch_deposit(&setup.user2, &entries),
+        Err(Ok(Error::UserCapExceeded))
+    );
+    assert_eq!(client.user_deposited(&setup.user2), 200);
+}
+
+#[test]
+fn test_withdraw_cooldown() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_cooldown_ledgers(&10);
+    assert_eq!(client.cooldown_ledgers(), 10);
+
+    setup.env.ledger().set_sequence_number(100);
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    assert_eq!(
+        client.try_redeem(&setup.user, &100, &setup.user, &setup.user),
+        Err(Ok(Error::Cooldown))
+    );
+    setup.env.ledger().set_sequence_number(109);
+    assert_eq!(
+        client.try_withdraw(&setup.user, &100, &setup.user, &setup.user),
+        Err(Ok(Error::Cooldown))
+    );
+
+    setup.env.ledger().set_sequence_number(110);
+    assert_eq!(client.redeem(&setup.user, &100, &setup.user, &setup.user), 100);
+}
+
+#[test]
+fn test_cooldown_locks_only_received_shares() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let user3 = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_cooldown_ledgers(&10);
+
+    setup.env.ledger().set_sequence_number(100);
+    client.deposit(&setup.user, &500, &setup.user);
+
+    // A third party's deposit locks only what it minted, not the victim's own shares
+    setup.env.ledger().set_sequence_number(110);
+    client.deposit(&setup.user2, &1, &setup.user);
+    assert_eq!(client.redeem(&setup.user, &500, &setup.user, &setup.user), 500);
+    assert_eq!(
+        client.try_redeem(&setup.user, &1, &setup.user, &setup.user),
+        Err(Ok(Error::Cooldown))
+    );
+
+    // Fresh shares can't skip the cooldown by moving to another account
+    client.deposit(&setup.user2, &300, &setup.user2);
+    assert_eq!(
+        client.try_transfer(&setup.user2, &user3, &300),
+        Err(Ok(Error::Cooldown))
+    );
+    client.deposit(&setup.user2, &200, &user3);
+    assert_eq!(
+        client.try_redeem(&user3, &200, &user3, &user3),
+        Err(Ok(Error::Cooldown))
+    );
+
+    setup.env.ledger().set_sequence_number(120);
+    client.transfer(&setup.user2, &user3, &300);
+    assert_eq!(
+        client.try_redeem(&user3, &300, &user3, &user3),
+        Err(Ok(Error::Cooldown))
+    );
+    assert_eq!(client.redeem(&user3, &200, &user3, &user3), 200);
+    assert_eq!(client.redeem(&setup.user, &1, &setup.user, &setup.user), 1);
+}
+
+#[test]
+fn test_cooldown_dust_transfers_dont_extend_lock() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    setup.env.ledger().set_sequence_number(100);
+    client.deposit(&setup.user2, &100, &setup.user2);
+    client.set_cooldown_ledgers(&100);
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    // One share every 99 ledgers used to restart the whole deposit's cooldown
+    for i in 1..=5u32 {
+        setup.env.ledger().set_sequence_number(100 + 99 * i);
+        client.transfer(&setup.user2, &setup.user, &1);
+    }
+    assert_eq!(client.balance_of(&setup.user), 1005);
+    assert_eq!(
+        client.try_redeem(&setup.user, &1005, &setup.user, &setup.user),
+        Err(Ok(Error::Cooldown))
+    );
+    // Only the last two dust receipts are still inside their own cooldown
+    assert_eq!(client.redeem(&setup.user, &1003, &setup.user, &setup.user), 1003);
+
+    // Flooding past the lot limit only folds into the newest receipt, never the older ones
+    setup.env.ledger().set_sequence_number(1000);
+    client.transfer(&setup.user2, &setup.user, &1);
+    setup.env.ledger().set_sequence_number(1001);
+    setup.mint_tokens(&setup.user, 500);
+    client.deposit(&setup.user, &500, &setup.user);
+    for i in 0..20u32 {
+        setup.env.ledger().set_sequence_number(1002 + i);
+        client.transfer(&setup.user2, &setup.user, &1);
+    }
+    setup.env.ledger().set_sequence_number(1100);
+    assert_eq!(client.redeem(&setup.user, &3, &setup.user, &setup.user), 3);
+    setup.env.ledger().set_sequence_number(1101);
+    assert_eq!(client.redeem(&setup.user, &500, &setup.user, &setup.user), 500);
+    assert_eq!(
+        client.try_redeem(&setup.user, &1, &setup.user, &setup.user),
+        Err(Ok(Error::Cooldown))
+    );
+}
+
+#[test]
+fn test_withdraw_cooldown_disabled() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.redeem(&setup.user, &100, &setup.user, &setup.user), 100);
+}
+
+#[test]
+fn test_dead_shares_first_deposit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 20_000);
+    setup.mint_tokens(&setup.user2, 20_000);
+
+    setup.env.mock_all_auths();
+    client.set_dead_shares(&true);
+    assert_eq!(client.preview_deposit(&10_000), 9_000);
+
+    assert_eq!(
+        client.try_deposit(&setup.user, &1_000, &setup.user),
+        Err(Ok(Error::ZeroShares))
+    );
+    assert_eq!(client.deposit(&setup.user, &10_000, &setup.user), 9_000);
+    assert_eq!(client.balance_of(&setup.vault_id), 1_000);
+    assert_eq!(client.total_supply(), 10_000);
+
+    // Later deposits are unaffected
+    assert_eq!(client.deposit(&setup.user2, &10_000, &setup.user2), 10_000);
+
+    // Nobody can move or redeem the dead shares
+    assert_eq!(
+        client.try_redeem(&setup.user, &1_000, &setup.user, &setup.vault_id),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    setup.env.set_auths(&[]);
+    assert!(client.try_transfer(&setup.vault_id, &setup.user, &1_000).is_err());
+    assert_eq!(client.balance_of(&setup.vault_id), 1_000);
+}
+
+#[test]
+fn test_initialize_with_deposit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 20_000);
+
+    setup.env.mock_all_auths();
+    let name = String::from_str(&setup.env, "Test Vault");
+    let symbol = String::from_str(&setup.env, "TVAULT");
+
+    // The seed has to cover the dead shares
+    assert_eq!(
+        client.try_initialize_with_deposit(&setup.admin, &setup.token_id, &name, &symbol, &7, &setup.user, &1_000),
+        Err(Ok(Error::ZeroShares))
+    );
+    assert!(!client.is_initialized());
+
+    let shares = client.initialize_with_deposit(&setup.admin, &setup.token_id, &name, &symbol, &7, &setup.user, &10_000);
+    assert_eq!(shares, 9_000);
+    assert!(client.is_initialized());
+    assert!(client.dead_shares());
+    assert_eq!(client.balance_of(&setup.user), 9_000);
+    assert_eq!(client.balance_of(&setup.vault_id), 1_000);
+    assert_eq!(client.total_supply(), 10_000);
+    assert_eq!(token_client.balance(&setup.vault_id), 10_000);
+    assert_eq!(
+        client.try_initialize_with_deposit(&setup.admin, &setup.token_id, &name, &symbol, &7, &setup.user, &10_000),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+}
+
+#[test]
+fn test_dead_shares_first_mint() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 20_000);
+
+    setup.env.mock_all_auths();
+    client.set_dead_shares(&true);
+
+    // The first minter pays for the dead shares on top of their own
+    assert_eq!(client.mint(&setup.user, &5_000, &setup.user), 6_000);
+    assert_eq!(client.balance_of(&setup.user), 5_000);
+    assert_eq!(client.balance_of(&setup.vault_id), 1_000);
+}
+
+#[test]
+fn test_withdraw_bounded_by_idle_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    // Move 400 out to a strategy and report it as deployed
+    let strategy = Address::generate(&setup.env);
+    token_client.transfer(&setup.vault_id, &strategy, &400);
+    client.set_deployed_assets(&400);
+
+    assert_eq!(client.idle_assets(), 600);
+    assert_eq!(client.deployed_assets(), 400);
+    assert_eq!(client.total_assets(), 1000);
+    assert_eq!(client.convert_to_assets(&1000), 1000);
+    assert_eq!(client.max_withdraw(&setup.user), 600);
+
+    assert_eq!(
+        client.try_withdraw(&setup.user, &601, &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+    assert_eq!(
+        client.try_redeem(&setup.user, &601, &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+
+    assert_eq!(client.withdraw(&setup.user, &600, &setup.user, &setup.user), 600);
+    assert_eq!(client.idle_assets(), 0);
+    assert_eq!(client.balance_of(&setup.user), 400);
+}
+
+#[test]
+fn test_max_withdraw_and_redeem_limited_by_idle_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_exit_fee(&setup.admin, &100);
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.max_redeem(&setup.user), 1000);
+    assert_eq!(client.max_withdraw(&setup.user), client.preview_redeem(&1000));
+
+    // Only 600 stays idle, less than the position is worth
+    let strategy = Address::generate(&setup.env);
+    token_client.transfer(&setup.vault_id, &strategy, &400);
+    client.set_deployed_assets(&400);
+
+    // 594 plus the 1% fee on top (6) uses up exactly the idle 600
+    let max_withdraw = client.max_withdraw(&setup.user);
+    let max_redeem = client.max_redeem(&setup.user);
+    assert_eq!(max_withdraw, 594);
+    assert_eq!(max_redeem, 600);
+    assert_eq!(
+        client.try_withdraw(&setup.user, &(max_withdraw + 1), &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+    assert_eq!(
+        client.try_redeem(&setup.user, &(max_redeem + 1), &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+
+    assert_eq!(client.withdraw(&setup.user, &max_withdraw, &setup.user, &setup.user), 600);
+    assert_eq!(client.idle_assets(), 0);
+    assert_eq!(client.max_withdraw(&setup.user), 0);
+    assert_eq!(client.max_redeem(&setup.user), 0);
+    assert_eq!(client.balance_of(&setup.user), 400);
+}
+
+#[test]
+fn test_report_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    let reporter = Address::generate(&setup.env);
+    assert_eq!(client.try_report_assets(&reporter, &50), Err(Ok(Error::Unauthorized)));
+    client.grant_role(&Symbol::new(&setup.env, "REPORTER"), &reporter);
+    // No bound, no reports
+    assert_eq!(client.try_report_assets(&reporter, &50), Err(Ok(Error::ReportBoundNotSet)));
+    client.set_max_report_delta_bps(&1_000);
+
+    // A gain within 10% of total assets is accepted and counted, but isn't idle
+    client.report_assets(&reporter, &80);
+    assert_eq!(client.reported_assets(), 80);
+    assert_eq!(client.total_assets(), 1080);
+    assert_eq!(client.idle_assets(), 1000);
+    assert_eq!(client.max_withdraw(&setup.user), 1000);
+    assert_eq!(client.last_known_assets(), 1080);
+
+    // A jump past 10% of 1080 is refused in either direction
+    assert_eq!(client.try_report_assets(&reporter, &189), Err(Ok(Error::ReportDeltaTooLarge)));
+    client.report_assets(&reporter, &188);
+    assert_eq!(client.try_report_assets(&reporter, &58), Err(Ok(Error::ReportDeltaTooLarge)));
+    assert_eq!(client.try_report_assets(&reporter, &-1), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.total_assets(), 1188);
+
+    // Clearing the bound stops reporting rather than lifting the limit
+    client.set_max_report_delta_bps(&0);
+    assert_eq!(client.try_report_assets(&reporter, &0), Err(Ok(Error::ReportBoundNotSet)));
+    assert_eq!(client.total_assets(), 1188);
+}
+
+#[test]
+fn test_strategy_deploy_and_harvest() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+
+    assert_eq!(client.try_deploy_to_strategy(&100), Err(Ok(Error::StrategyNotSet)));
+    client.set_strategy(&strategy_id);
+    assert_eq!(client.strategy(), Some(strategy_id.clone()));
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(
+        client.try_deploy_to_strategy(&1001),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+    client.deploy_to_strategy(&600);
+    assert_eq!(client.idle_assets(), 400);
+    assert_eq!(client.deployed_assets(), 600);
+    assert_eq!(client.total_assets(), 1000);
+
+    let price_before = client.price_per_share();
+
+    // The strategy earns 100
+    setup.mint_tokens(&strategy_id, 100);
+    assert_eq!(client.harvest(), 100);
+
+    assert_eq!(client.idle_assets(), 500);
+    assert_eq!(client.deployed_assets(), 600);
+    assert_eq!(client.total_assets(), 1100);
+    assert!(client.price_per_share() > price_before);
+    assert_eq!(client.preview_redeem(&1000), 1099);
+}
+
+#[test]
+fn test_withdraw_from_strategy() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+
+    assert_eq!(client.try_withdraw_from_strategy(&100), Err(Ok(Error::StrategyNotSet)));
+    client.set_strategy(&strategy_id);
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deploy_to_strategy(&600);
+    assert_eq!(
+        client.try_redeem(&setup.user, &1000, &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+
+    // A strategy that sends back less than asked can't have the gap written off
+    let strategy_client = strategy::MockStrategyClient::new(&setup.env, &strategy_id);
+    strategy_client.set_shortfall(&1);
+    assert_eq!(client.try_withdraw_from_strategy(&600), Err(Ok(Error::StrategyShortfall)));
+    setup.mint_tokens(&strategy_id, 100);
+    assert_eq!(client.try_harvest(), Err(Ok(Error::StrategyShortfall)));
+    assert_eq!(client.deployed_assets(), 600);
+    token_client.transfer(&strategy_id, &setup.admin, &100);
+    strategy_client.set_shortfall(&0);
+
+    assert_eq!(client.try_withdraw_from_strategy(&0), Err(Ok(Error::ZeroAssets)));
+    assert_eq!(client.try_withdraw_from_strategy(&-1), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_withdraw_from_strategy(&601), Err(Ok(Error::InsufficientVaultAssets)));
+
+    client.withdraw_from_strategy(&600);
+    assert_eq!(client.idle_assets(), 1000);
+    assert_eq!(client.deployed_assets(), 0);
+    assert_eq!(client.total_assets(), 1000);
+    assert_eq!(token_client.balance(&strategy_id), 0);
+
+    assert_eq!(client.redeem(&setup.user, &1000, &setup.user, &setup.user), 1000);
+    assert_eq!(token_client.balance(&setup.user), 1000);
+}
+
+#[test]
+fn test_strategy_harvest_loss() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deploy_to_strategy(&600);
+
+    // The strategy loses 200
+    token_client.transfer(&strategy_id, &setup.admin, &200);
+    assert_eq!(client.harvest(), 0);
+    assert_eq!(client.deployed_assets(), 400);
+    assert_eq!(client.total_assets(), 800);
+}
+
+#[test]
+fn test_queued_withdrawal_loss_floors_total_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 2000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deposit(&setup.user2, &1000, &setup.user2);
+    client.deploy_to_strategy(&1800);
+    client.request_withdraw(&setup.user, &1000, &setup.user, &setup.user);
+
+    // The 200 left idle is owed to the queued request
+    assert_eq!(client.try_deploy_to_strategy(&100), Err(Ok(Error::InsufficientIdleAssets)));
+
+    // The strategy loses 90%, more than the remaining holders' stake
+    token_client.transfer(&strategy_id, &setup.admin, &1620);
+    client.harvest();
+    assert_eq!(client.total_assets(), 0);
+    assert_eq!(client.convert_to_assets(&1000), 0);
+
+    let status = client.status();
+    assert_eq!(status.idle_assets, 200);
+    assert!(!status.solvent);
+    // A wiped-out vault refuses deposits cleanly instead of trapping on the cap arithmetic
+    assert_eq!(client.try_deposit(&setup.user2, &100, &setup.user2), Err(Ok(Error::ZeroShares)));
+}
+
+#[test]
+fn test_harvest_reports_profit_and_loss() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+    client.set_performance_fee(&setup.admin, &2_000);
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deploy_to_strategy(&600);
+
+    // Gain: the profit is reported and a fee is minted
+    setup.mint_tokens(&strategy_id, 100);
+    assert_eq!(client.harvest(), 100);
+    // Followed by `rate_updated`
+    let events = setup.env.events().all();
+    let (_, topics, data) = events.get(events.len() - 2).unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("v1"), Symbol::new(&setup.env, "harvested"), setup.admin.clone(), strategy_id.clone()).into_val(&setup.env)
+    );
+    let event: HarvestEvent = data.into_val(&setup.env);
+    assert_eq!(event, HarvestEvent { profit: 100, loss: 0, total_assets_after: 1100 });
+    let fee_shares = client.balance_of(&setup.admin);
+    assert!(fee_shares > 0);
+
+    // Loss: written down in full, no fee, and the price drops
+    let price_before = client.price_per_share();
+    token_client.transfer(&strategy_id, &setup.user2, &200);
+    assert_eq!(client.harvest(), 0);
+    let events = setup.env.events().all();
+    let (_, _, data) = events.get(events.len() - 2).unwrap();
+    let event: HarvestEvent = data.into_val(&setup.env);
+    assert_eq!(event, HarvestEvent { profit: 0, loss: 200, total_assets_after: 900 });
+    assert_eq!(client.balance_of(&setup.admin), fee_shares);
+    assert!(client.price_per_share() < price_before);
+}
+
+#[test]
+fn test_rate_updated_only_when_rate_moves() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+
+    let rate_topics = (symbol_short!("v1"), Symbol::new(&setup.env, "rate_updated")).into_val(&setup.env);
+    let rate_events = |env: &Env| {
+        env.events().all().iter().filter(|(_, topics, _)| *topics == rate_topics).count()
+    };
+
+    // Entering at the current rate leaves it where it was
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(rate_events(&setup.env), 0);
+    client.deploy_to_strategy(&600);
+
+    setup.mint_tokens(&strategy_id, 100);
+    client.harvest();
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        rate_topics.clone(),
+        (1100i128, 1000i128).into_val(&setup.env),
+    )));
+
+    client.transfer(&setup.user, &setup.user2, &100);
+    assert_eq!(rate_events(&setup.env), 0);
+
+    // Nothing left to harvest, so the rate is unchanged
+    client.harvest();
+    assert_eq!(rate_events(&setup.env), 0);
+
+    // A flash loan fee stays with holders
+    let borrower = setup.env.register(flash_borrower::MockBorrower, ());
+    flash_borrower::MockBorrowerClient::new(&setup.env, &borrower).initialize(&setup.vault_id, &true);
+    setup.mint_tokens(&borrower, 40);
+    client.set_flash_fee(&setup.admin, &1_000);
+    client.flash_loan(&borrower, &400, &Bytes::new(&setup.env));
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        rate_topics.clone(),
+        (1140i128, 1000i128).into_val(&setup.env),
+    )));
+
+    // Burning shares hands their assets to everyone else
+    client.burn(&setup.user, &100);
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        rate_topics.clone(),
+        (1140i128, 900i128).into_val(&setup.env),
+    )));
+}
+
+#[test]
+fn test_fee_config() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.env.mock_all_auths();
+
+    assert_eq!(
+        client.fee_config(),
+        FeeConfig {
+            entry_bps: 0,
+            exit_bps: 0,
+            performance_bps: 0,
+            management_bps: 0,
+            flash_bps: 0,
+            recipient: setup.admin.clone(),
+        }
+    );
+
+    let treasury = Address::generate(&setup.env);
+    client.set_entry_fee(&setup.admin, &50);
+    client.set_performance_fee(&setup.admin, &1_000);
+    client.set_fee_recipient(&setup.admin, &treasury);
+    assert_eq!(
+        client.fee_config(),
+        FeeConfig {
+            entry_bps: 50,
+            exit_bps: 0,
+            performance_bps: 1_000,
+            management_bps: 0,
+            flash_bps: 0,
+            recipient: treasury,
+        }
+    );
+}
+
+#[test]
+fn test_checkpoints() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.latest_checkpoint(), None);
+
+    setup.env.ledger().set_sequence_number(10);
+    client.deposit(&setup.user, &100, &setup.user);
+    assert_eq!(client.checkpoint(), 0);
+
+    // Yield accrues between checkpoints
+    setup.env.ledger().set_sequence_number(20);
+    setup.mint_tokens(&setup.vault_id, 50);
+    assert_eq!(client.checkpoint(), 1);
+
+    setup.env.ledger().set_sequence_number(30);
+    client.deposit(&setup.user, &300, &setup.user);
+    assert_eq!(client.checkpoint(), 2);
+
+    assert_eq!(
+        client.get_checkpoint(&0),
+        Some(Checkpoint { ledger: 10, total_assets: 100, total_supply: 100 })
+    );
+    assert_eq!(
+        client.get_checkpoint(&1),
+        Some(Checkpoint { ledger: 20, total_assets: 150, total_supply: 100 })
+    );
+    assert_eq!(client.latest_checkpoint(), client.get_checkpoint(&2));
+    assert_eq!(client.latest_checkpoint().unwrap().ledger, 30);
+    assert_eq!(client.get_checkpoint(&3), None);
+}
+
+#[test]
+fn test_checkpoint_window_evicts_oldest() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_set_checkpoint_window(&0), Err(Ok(Error::InvalidCheckpointWindow)));
+    client.set_checkpoint_window(&3);
+
+    for sequence in 1..=5 {
+        setup.env.ledger().set_sequence_number(sequence);
+        client.checkpoint();
+    }
+
+    assert_eq!(client.checkpoint_count(), 5);
+    assert_eq!(client.get_checkpoint(&0), None);
+    assert_eq!(client.get_checkpoint(&1), None);
+    assert_eq!(client.get_checkpoint(&2).unwrap().ledger, 3);
+    assert_eq!(client.latest_checkpoint().unwrap().ledger, 5);
+}
+
+#[test]
+fn test_redeem_all() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.transfer(&setup.user, &setup.user2, &300);
+    setup.mint_tokens(&setup.vault_id, 100);
+
+    let expected = client.preview_redeem(&700);
+    assert_eq!(client.redeem_all(&setup.user, &setup.user, &setup.user), expected);
+    assert_eq!(client.balance_of(&setup.user), 0);
+    assert_eq!(client.balance_of(&setup.user2), 300);
+    assert_eq!(client.total_supply(), 300);
+
+    assert_eq!(
+        client.try_redeem_all(&setup.user, &setup.user, &setup.user),
+        Err(Ok(Error::ZeroShares))
+    );
+}
+
+#[test]
+fn test_withdraw_with_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 2000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    // Double the share price so assets and shares differ
+    token_client.transfer(&setup.user, &setup.vault_id, &1000);
+    client.approve(&setup.user, &setup.user2, &150, &u32::MAX);
+
+    // The allowance is in shares: 200 assets at ~2 per share burns 101 shares
+    let shares = client.withdraw(&setup.user2, &200, &setup.user2, &setup.user);
+    assert_eq!(shares, 101);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 150 - shares);
+    assert_eq!(client.balance_of(&setup.user), 1000 - shares);
+    assert_eq!(token_client.balance(&setup.user2), 200);
+
+    // 49 shares left is not enough for another 101
+    assert_eq!(
+        client.try_withdraw(&setup.user2, &200, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 49);
+}
+
+#[test]
+fn test_redeem_with_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 2000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    token_client.transfer(&setup.user, &setup.vault_id, &1000);
+    client.approve(&setup.user, &setup.user2, &150, &u32::MAX);
+
+    let assets = client.redeem(&setup.user2, &100, &setup.user2, &setup.user);
+    assert_eq!(assets, 199);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 50);
+    assert_eq!(client.balance_of(&setup.user), 900);
+    assert_eq!(token_client.balance(&setup.user2), 199);
+
+    assert_eq!(
+        client.try_redeem(&setup.user2, &51, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    client.redeem(&setup.user2, &50, &setup.user2, &setup.user);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+    assert_eq!(client.balance_of(&setup.user), 850);
+}
+
+#[test]
+fn test_redeem_and_deposit_into_another_vault() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    let target_id = setup.env.register(VaultContract, ());
+    let target = VaultContractClient::new(&setup.env, &target_id);
+    target.initialize(
+        &setup.admin,
+        &setup.token_id,
+        &String::from_str(&setup.env, "Target Vault"),
+        &String::from_str(&setup.env, "TTARGET"),
+        &18,
+        &0,
+        &None,
+    );
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    assert_eq!(client.redeem_and_deposit(&600, &setup.user, &target_id), 600);
+    assert_eq!(client.balance_of(&setup.user), 400);
+    assert_eq!(target.balance_of(&setup.user), 600);
+    assert_eq!(token_client.balance(&setup.user), 0);
+    assert_eq!(token_client.balance(&setup.vault_id), 400);
+    assert_eq!(token_client.balance(&target_id), 600);
+
+    // A vault over another asset, or no vault at all, is refused before anything moves
+    let other_token = setup.env.register(token::MockToken, ());
+    let other_id = setup.env.register(VaultContract, ());
+    VaultContractClient::new(&setup.env, &other_id).initialize(
+        &setup.admin,
+        &other_token,
+        &String::from_str(&setup.env, "Other Vault"),
+        &String::from_str(&setup.env, "TOTHER"),
+        &18,
+        &0,
+        &None,
+    );
+    assert_eq!(client.try_redeem_and_deposit(&100, &setup.user, &other_id), Err(Ok(Error::AssetMismatch)));
+    assert_eq!(
+        client.try_redeem_and_deposit(&100, &setup.user, &setup.token_id),
+        Err(Ok(Error::AssetMismatch))
+    );
+    assert_eq!(
+        client.try_redeem_and_deposit(&100, &setup.user, &setup.vault_id),
+        Err(Ok(Error::InvalidAddress))
+    );
+    assert_eq!(client.balance_of(&setup.user), 400);
+}
+
+#[test]
+fn test_redeem_all_requires_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &500, &setup.user);
+    client.approve(&setup.user, &setup.user2, &499, &u32::MAX);
+
+    assert_eq!(
+        client.try_redeem_all(&setup.user2, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+
+    client.approve(&setup.user, &setup.user2, &500, &u32::MAX);
+    assert_eq!(client.redeem_all(&setup.user2, &setup.user2, &setup.user), 500);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+}
+
+#[test]
+fn test_permit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    setup.env.mock_all_auths();
+    let (signing_key, owner) = permit_signer(&setup.env, 7);
+    setup.mint_tokens(&owner, 1000);
+    client.deposit(&owner, &500, &owner);
+
+    setup.env.ledger().set_sequence_number(100);
+    let signature = sign_permit(&setup.env, &signing_key, &setup.vault_id, &owner, &setup.user2, 200, 150, 0);
+
+    // No authorization from the owner is needed, only the signature
+    setup.env.set_auths(&[]);
+    client.permit(&owner, &setup.user2, &200, &150, &0, &signature);
+    assert_eq!(client.allowance(&owner, &setup.user2), 200);
+
+    setup.env.mock_all_auths();
+    client.transfer_from(&setup.user2, &owner, &setup.user2, &200);
+    assert_eq!(client.balance_of(&setup.user2), 200);
+}
+
+#[test]
+fn test_permit_expired() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let (signing_key, owner) = permit_signer(&setup.env, 7);
+    setup.env.ledger().set_sequence_number(100);
+    let signature = sign_permit(&setup.env, &signing_key, &setup.vault_id, &owner, &setup.user2, 200, 99, 0);
+
+    assert_eq!(
+        client.try_permit(&owner, &setup.user2, &200, &99, &0, &signature),
+        Err(Ok(Error::PermitExpired))
+    );
+    assert_eq!(client.allowance(&owner, &setup.user2), 0);
+}
+
+#[test]
+fn test_permit_replay_rejected() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let (signing_key, owner) = permit_signer(&setup.env, 7);
+    let signature = sign_permit(&setup.env, &signing_key, &setup.vault_id, &owner, &setup.user2, 200, 150, 0);
+    client.permit(&owner, &setup.user2, &200, &150, &0, &signature);
+
+    assert_eq!(
+        client.try_permit(&owner, &setup.user2, &200, &150, &0, &signature),
+        Err(Ok(Error::InvalidNonce))
+    );
+}
+
+#[test]
+fn test_permit_invalid_signature() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let (_, owner) = permit_signer(&setup.env, 7);
+    let (other_key, _) = permit_signer(&setup.env, 8);
+    let signature = sign_permit(&setup.env, &other_key, &setup.vault_id, &owner, &setup.user2, 200, 150, 0);
+
+    assert!(client.try_permit(&owner, &setup.user2, &200, &150, &0, &signature).is_err());
+    assert_eq!(client.allowance(&owner, &setup.user2), 0);
+
+    // Contract addresses have no key to sign with
+    let contract_owner = setup.token_id.clone();
+    assert_eq!(
+        client.try_permit(&contract_owner, &setup.user2, &200, &150, &0, &signature),
+        Err(Ok(Error::InvalidAddress))
+    );
+}
+
+#[test]
+fn test_nonces() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let (signing_key, owner) = permit_signer(&setup.env, 7);
+    assert_eq!(client.nonces(&owner), 0);
+
+    let signature = sign_permit(&setup.env, &signing_key, &setup.vault_id, &owner, &setup.user2, 200, 150, 0);
+    client.permit(&owner, &setup.user2, &200, &150, &0, &signature);
+    assert_eq!(client.nonces(&owner), 1);
+
+    // A rejected permit leaves the nonce alone
+    assert_eq!(
+        client.try_permit(&owner, &setup.user2, &200, &150, &0, &signature),
+        Err(Ok(Error::InvalidNonce))
+    );
+    assert_eq!(client.nonces(&owner), 1);
+
+    let signature = sign_permit(&setup.env, &signing_key, &setup.vault_id, &owner, &setup.user2, 300, 150, 1);
+    client.permit(&owner, &setup.user2, &300, &150, &1, &signature);
+    assert_eq!(client.nonces(&owner), 2);
+    assert_eq!(client.nonces(&setup.user2), 0);
+
+    setup.env.as_contract(&setup.vault_id, || {
+        let ttl = setup.env.storage().persistent().get_ttl(&DataKey::Nonce(owner.clone()));
+        assert!(ttl >= NONCE_TTL_EXTEND_TO);
+    });
+}
+
+#[test]
+fn test_convert_at_overrides() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", 18, 3);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &700, &setup.user);
+    setup.mint_tokens(&setup.vault_id, 123);
+
+    // Fed the live state, the override functions match the live ones
+    let total = client.total_assets();
+    let supply = client.total_supply();
+    for amount in [0i128, 1, 7, 999, 123_456] {
+        assert_eq!(client.convert_to_shares_at(&amount, &total, &supply), client.convert_to_shares(&amount));
+        assert_eq!(client.convert_to_assets_at(&amount, &total, &supply), client.convert_to_assets(&amount));
+    }
+
+    // A hypothetical state doesn't touch the real one
+    assert_eq!(client.convert_to_shares_at(&100, &0, &0), 100_000);
+    assert_eq!(client.convert_to_assets_at(&100_000, &0, &0), 100);
+    assert_eq!(client.total_assets(), total);
+    assert_eq!(client.total_supply(), supply);
+
+    assert_eq!(client.try_convert_to_shares_at(&100, &-1, &0), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_convert_to_assets_at(&100, &0, &-1), Err(Ok(Error::InvalidAmount)));
+}
+
+#[test]
+fn test_withdraw_queue() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    // Only 200 stays idle
+    let strategy = Address::generate(&setup.env);
+    token_client.transfer(&setup.vault_id, &strategy, &800);
+    client.set_deployed_assets(&800);
+    assert_eq!(
+        client.try_redeem(&setup.user, &500, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+
+    setup.env.ledger().set_sequence_number(42);
+    let id = client.request_withdraw(&setup.user, &500, &setup.user2, &setup.user);
+    assert_eq!(
+        client.withdraw_request(&id),
+        Some(WithdrawRequest {
+            owner: setup.user.clone(),
+            receiver: setup.user2.clone(),
+            shares: 500,
+            assets: 500,
+            fee: 0,
+            ledger: 42,
+        })
+    );
+    assert_eq!(client.balance_of(&setup.user), 500);
+    assert_eq!(client.pending_withdrawals(), 500);
+    assert_eq!(client.total_assets(), 500);
+    assert_eq!(client.convert_to_assets(&500), 500);
+
+    assert_eq!(client.try_fulfill_withdraw(&id), Err(Ok(Error::InsufficientIdleAssets)));
+
+    // The strategy returns 300, leaving 500 idle
+    token_client.transfer(&strategy, &setup.vault_id, &300);
+    client.set_deployed_assets(&500);
+
+    assert_eq!(client.fulfill_withdraw(&id), 500);
+    assert_eq!(token_client.balance(&setup.user2), 500);
+    assert_eq!(client.pending_withdrawals(), 0);
+    assert_eq!(client.total_assets(), 500);
+    assert_eq!(client.withdraw_request(&id), None);
+    assert_eq!(client.try_fulfill_withdraw(&id), Err(Ok(Error::RequestNotFound)));
+}
+
+#[test]
+fn test_asset_balance_breakdown() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.asset_balance_breakdown(), (1000, 0, 0));
+
+    client.request_withdraw(&setup.user, &200, &setup.user, &setup.user);
+    assert_eq!(client.asset_balance_breakdown(), (1000, 200, 0));
+
+    client.deploy_to_strategy(&500);
+    let (raw, pending, deployed) = client.asset_balance_breakdown();
+    assert_eq!((raw, pending, deployed), (500, 200, 500));
+    assert_eq!(raw - pending + deployed, client.total_assets());
+}
+
+#[test]
+fn test_withdraw_queue_requires_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    assert_eq!(
+        client.try_request_withdraw(&setup.user2, &100, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+
+    client.approve(&setup.user, &setup.user2, &100, &u32::MAX);
+    let id = client.request_withdraw(&setup.user2, &100, &setup.user2, &setup.user);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+    assert_eq!(client.fulfill_withdraw(&id), 100);
+}
+
+#[test]
+fn test_solvency() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.solvency_ratio(), 10_000);
+    assert!(client.is_solvent());
+
+    client.deposit(&setup.user, &400, &setup.user);
+    assert_eq!(client.solvency_ratio(), 10_000);
+
+    // A strategy loss takes 300 of the 400 backing
+    let sink = Address::generate(&setup.env);
+    token_client.transfer(&setup.vault_id, &sink, &300);
+    assert_eq!(client.solvency_ratio(), 2_500);
+    assert!(client.is_solvent());
+
+    // Without a minimum, deposits still go through
+    client.deposit(&setup.user, &100, &setup.user);
+
+    client.set_min_solvency_bps(&5_000);
+    assert!(!client.is_solvent());
+    assert_eq!(
+        client.try_deposit(&setup.user, &100, &setup.user),
+        Err(Ok(Error::Insolvent))
+    );
+    assert_eq!(
+        client.try_mint(&setup.user, &100, &setup.user),
+        Err(Ok(Error::Insolvent))
+    );
+
+    // Fully drained
+    token_client.transfer(&setup.vault_id, &sink, &client.total_assets());
+    assert_eq!(client.solvency_ratio(), 0);
+    client.set_min_solvency_bps(&0);
+    assert!(!client.is_solvent());
+}
+
+#[test]
+fn test_asset_basket() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.assets(), vec![&setup.env, setup.token_id.clone()]);
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.total_assets(), 1000);
+
+    // A second token worth two units of the primary asset each
+    let second_id = setup.env.register(token::MockToken, ());
+    let second = token::MockTokenClient::new(&setup.env, &second_id);
+    second.mint(&setup.vault_id, &100);
+    assert_eq!(client.total_assets(), 1000);
+
+    assert_eq!(client.try_add_asset(&setup.token_id, &10_000), Err(Ok(Error::InvalidAddress)));
+    assert_eq!(client.try_add_asset(&second_id, &0), Err(Ok(Error::InvalidWeight)));
+    client.add_asset(&second_id, &20_000);
+    assert_eq!(client.assets(), vec![&setup.env, setup.token_id.clone(), second_id.clone()]);
+    assert_eq!(client.asset_weight(&second_id), Some(20_000));
+
+    assert_eq!(client.total_assets(), 1200);
+    assert_eq!(client.convert_to_assets(&1000), 1199);
+
+    // Only the primary asset can be paid out
+    assert_eq!(client.max_withdraw(&setup.user), 1000);
+    assert_eq!(
+        client.try_withdraw(&setup.user, &1001, &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+    assert_eq!(client.try_sweep(&second_id, &setup.admin, &100), Err(Ok(Error::CannotSweepAsset)));
+
+    client.remove_asset(&second_id);
+    assert_eq!(client.total_assets(), 1000);
+    assert_eq!(client.asset_weight(&second_id), None);
+    assert_eq!(client.try_remove_asset(&second_id), Err(Ok(Error::AssetNotRegistered)));
+}
+
+#[test]
+fn test_asset_basket_overflow() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    let second_id = setup.env.register(token::MockToken, ());
+    client.add_asset(&second_id, &10_000);
+
+    // A balance too large to value is reported, not aborted on
+    token::MockTokenClient::new(&setup.env, &second_id).mint(&setup.vault_id, &(i128::MAX / 2));
+    assert_eq!(client.try_total_assets(), Err(Ok(Error::Overflow)));
+    assert_eq!(
+        client.try_redeem(&setup.user, &100, &setup.user, &setup.user),
+        Err(Ok(Error::Overflow))
+    );
+
+    // Dropping the asset unblocks exits
+    client.remove_asset(&second_id);
+    assert_eq!(client.redeem(&setup.user, &100, &setup.user, &setup.user), 100);
+}
+
+#[test]
+fn test_quote_values_with_oracle() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    // No oracle: values stay in asset units
+    assert_eq!(client.oracle(), None);
+    assert_eq!(client.total_assets_in_quote(), 1000);
+    assert_eq!(client.price_per_share_in_quote(), client.price_per_share());
+
+    // The asset is worth 2.0 quote units
+    let oracle_id = setup.env.register(oracle::MockOracle, ());
+    oracle::MockOracleClient::new(&setup.env, &oracle_id).set_price(&setup.token_id, &20_000_000);
+    client.set_oracle(&oracle_id);
+
+    assert_eq!(client.total_assets_in_quote(), 2000);
+    assert_eq!(client.price_per_share_in_quote(), client.price_per_share() * 2);
+}
+
+#[test]
+fn test_performance_fee_high_water_mark() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_set_performance_fee(&setup.admin, &3_001), Err(Ok(Error::InvalidFee)));
+    client.set_performance_fee(&setup.admin, &2_000);
+    assert_eq!(client.high_water_mark(), 10_000_000);
+
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deploy_to_strategy(&600);
+
+    // Price rises from 1.0 to ~1.1: 20% of the ~100 gain is taken as shares
+    setup.mint_tokens(&strategy_id, 100);
+    client.harvest();
+    let fee_shares = client.balance_of(&setup.admin);
+    assert_eq!(fee_shares, 17);
+    assert_eq!(client.convert_to_assets(&fee_shares), 18);
+    let mark = client.high_water_mark();
+    assert_eq!(mark, client.price_per_share());
+    assert!(mark > 10_000_000);
+
+    // A loss drops the price below the mark; the mark stays put
+    token_client.transfer(&strategy_id, &setup.user2, &200);
+    client.harvest();
+    assert!(client.price_per_share() < mark);
+    assert_eq!(client.high_water_mark(), mark);
+    assert_eq!(client.balance_of(&setup.admin), fee_shares);
+
+    // Recovering the loss only returns to the mark, so no fee is charged again
+    setup.mint_tokens(&strategy_id, 200);
+    client.harvest();
+    assert_eq!(client.price_per_share(), mark);
+    assert_eq!(client.balance_of(&setup.admin), fee_shares);
+    assert_eq!(client.high_water_mark(), mark);
+
+    // A new high is charged and advances the mark
+    setup.mint_tokens(&strategy_id, 100);
+    client.harvest();
+    assert!(client.balance_of(&setup.admin) > fee_shares);
+    assert!(client.high_water_mark() > mark);
+}
+
+#[test]
+fn test_management_fee_accrual() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+    setup.initialize_token(10_000_000);
+    setup.mint_tokens(&setup.user, 2_000_000);
+
+    // Keep the balance history written below live across the jump
+    let half_year = 17_280 * 365 / 2;
+    setup.env.ledger().with_mut(|li| li.min_persistent_entry_ttl = half_year + 1);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_set_management_fee(&setup.admin, &10_001), Err(Ok(Error::InvalidFee)));
+    client.set_management_fee(&setup.admin, &200);
+    client.deposit(&setup.user, &1_000_000, &setup.user);
+
+    // Keep both instances live across the jump
+    for id in [&setup.vault_id, &setup.token_id] {
+        setup.env.as_contract(id, || setup.env.storage().instance().extend_ttl(half_year, half_year + 1));
+    }
+
+    // Half a year at 2% accrues 1% of 1_000_000 = 10_000 assets of fee, which takes
+    // 10_000 * 1_000_001 / 990_001 = 10_101 shares once minted
+    setup.env.ledger().set_sequence_number(half_year);
+    client.deposit(&setup.user, &1, &setup.user);
+    assert_eq!(client.balance_of(&setup.admin), 10_101);
+    // Worth just under 10_000 after rounding down
+    assert_eq!(client.convert_to_assets(&10_101), 9_999);
+
+    // A second call in the same ledger accrues nothing
+    client.deposit(&setup.user, &1, &setup.user);
+    client.redeem(&setup.user, &10, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.admin), 10_101);
+}
+
+#[test]
+fn test_simulate_deposit_matches_deposit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    setup.mint_tokens(&setup.vault_id, 300);
+
+    // No fees
+    let simulation = client.simulate_deposit(&500);
+    assert_eq!(simulation.entry_fee, 0);
+    assert_eq!(simulation.net_assets, 500);
+    assert_eq!(client.deposit(&setup.user, &500, &setup.user2), simulation.shares);
+    assert_eq!(client.price_per_share(), simulation.new_price_per_share);
+
+    // 1% entry fee
+    client.set_entry_fee(&setup.admin, &100);
+    let simulation = client.simulate_deposit(&1010);
+    assert_eq!(simulation.entry_fee, 10);
+    assert_eq!(simulation.net_assets, 1000);
+
+    let recipient_before = token_client.balance(&setup.admin);
+    let total_before = client.total_assets();
+    assert_eq!(client.deposit(&setup.user, &1010, &setup.user), simulation.shares);
+    assert_eq!(token_client.balance(&setup.admin) - recipient_before, simulation.entry_fee);
+    assert_eq!(client.total_assets() - total_before, simulation.net_assets);
+    assert_eq!(client.price_per_share(), simulation.new_price_per_share);
+}
+
+#[test]
+fn test_event_sequence_numbers() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.current_event_seq(), 0);
+
+    // `events().all()` only covers the last invocation, so collect after each call
+    let share_events = |env: &Env| -> std::vec::Vec<u64> {
+        env.events()
+            .all()
+            .iter()
+            .filter(|(contract, topics, _)| {
+                let name: Symbol = topics.get(1).unwrap().into_val(env);
+                *contract == setup.vault_id
+                    && [symbol_short!("transfer"), symbol_short!("mint"), symbol_short!("burn")].contains(&name)
+            })
+            .map(|(_, _, data)| {
+                let (_, seq): (i128, u64) = data.into_val(env);
+                seq
+            })
+            .collect()
+    };
+
+    let mut seqs = std::vec::Vec::new();
+    client.deposit(&setup.user, &500, &setup.user);
+    seqs.extend(share_events(&setup.env));
+    client.transfer(&setup.user, &setup.user2, &100);
+    seqs.extend(share_events(&setup.env));
+    client.burn(&setup.user2, &10);
+    seqs.extend(share_events(&setup.env));
+    client.redeem(&setup.user, &50, &setup.user, &setup.user);
+    seqs.extend(share_events(&setup.env));
+
+    assert_eq!(seqs, std::vec![1, 2, 3, 4]);
+    assert_eq!(client.current_event_seq(), 4);
+}
+
+#[test]
+fn test_admin_mint_unbacked_shares() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    let treasury = Address::generate(&setup.env);
+    assert_eq!(client.try_admin_mint(&treasury, &1), Err(Ok(Error::UnbackedCapExceeded)));
+
+    client.set_max_unbacked(&500);
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.admin_mint(&treasury, &500);
+    assert_eq!(client.unbacked_shares(), 500);
+    assert_eq!(client.balance_of(&treasury), 500);
+    assert_eq!(client.total_supply(), 1500);
+    assert_eq!(client.total_assets(), 1000);
+    assert_eq!(client.try_admin_mint(&treasury, &1), Err(Ok(Error::UnbackedCapExceeded)));
+
+    // A later deposit gets shares worth what it put in: 300 * 1501 / 1001 = 449.85
+    let shares = client.deposit(&setup.user2, &300, &setup.user2);
+    assert_eq!(shares, 449);
+    assert_eq!(client.preview_redeem(&shares), 299);
+}
+
+#[test]
+fn test_mint_past_max_supply_returns_error() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    setup.env.mock_all_auths();
+    setup.env.as_contract(&setup.vault_id, || {
+        setup.env.storage().instance().set(&DataKey::TotalSupply, &(i128::MAX - 10));
+    });
+    client.set_max_unbacked(&i128::MAX);
+
+    let treasury = Address::generate(&setup.env);
+    assert_eq!(client.try_admin_mint(&treasury, &11), Err(Ok(Error::SupplyOverflow)));
+    assert_eq!(client.total_supply(), i128::MAX - 10);
+    assert_eq!(client.balance_of(&treasury), 0);
+
+    client.admin_mint(&treasury, &10);
+    assert_eq!(client.total_supply(), i128::MAX);
+    assert_eq!(client.balance_of(&treasury), 10);
+}
+
+#[test]
+fn test_allowlist_disabled_lets_anyone_in() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_allowlist(&setup.user, &true);
+    assert!(!client.allowlist_enabled());
+    assert!(client.is_allowlisted(&setup.user));
+    assert!(!client.is_allowlisted(&setup.user2));
+
+    client.deposit(&setup.user, &500, &setup.user);
+    client.deposit(&setup.user2, &500, &setup.user2);
+    client.mint(&setup.user2, &100, &setup.user2);
+    assert_eq!(client.max_redeem(&setup.user2), 600);
+    client.redeem(&setup.user2, &600, &setup.user2, &setup.user2);
+    client.withdraw(&setup.user, &500, &setup.user, &setup.user);
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+fn test_allowlist_enabled_gates_entry_and_exit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user2, &300, &setup.user2);
+    client.set_allowlist(&setup.user, &true);
+    client.set_allowlist_enabled(&true);
+
+    // Not allowlisted: no deposits or mints, and no way out of an earlier position
+    assert_eq!(client.max_deposit(&setup.user2), 0);
+    assert_eq!(client.max_mint(&setup.user2), 0);
+    assert_eq!(client.max_withdraw(&setup.user2), 0);
+    assert_eq!(client.max_redeem(&setup.user2), 0);
+    assert_eq!(client.try_deposit(&setup.user2, &100, &setup.user2), Err(Ok(Error::NotAllowlisted)));
+    assert_eq!(client.try_mint(&setup.user2, &100, &setup.user2), Err(Ok(Error::NotAllowlisted)));
+    assert_eq!(
+        client.try_withdraw(&setup.user2, &100, &setup.user2, &setup.user2),
+        Err(Ok(Error::NotAllowlisted))
+    );
+    assert_eq!(
+        client.try_redeem(&setup.user2, &100, &setup.user2, &setup.user2),
+        Err(Ok(Error::NotAllowlisted))
+    );
+    assert_eq!(
+        client.try_request_withdraw(&setup.user2, &100, &setup.user2, &setup.user2),
+        Err(Ok(Error::NotAllowlisted))
+    );
+
+    // The receiver is what counts, not who pays
+    assert_eq!(client.try_deposit(&setup.user, &100, &setup.user2), Err(Ok(Error::NotAllowlisted)));
+    assert_eq!(
+        client.try_batch_deposit(&setup.user, &vec![&setup.env, (100, setup.user.clone()), (100, setup.user2.clone())]),
+        Err(Ok(Error::NotAllowlisted))
+    );
+
+    // Allowlisted: everything works, and can pay out to a non-allowlisted receiver
+    client.deposit(&setup.user2, &200, &setup.user);
+    client.mint(&setup.user, &100, &setup.user);
+    client.withdraw(&setup.user, &100, &setup.user2, &setup.user);
+    client.redeem(&setup.user, &200, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.user), 0);
+
+    // Removing an address takes effect immediately; disabling the list lets it back in
+    client.set_allowlist(&setup.user, &false);
+    assert_eq!(client.try_deposit(&setup.user, &100, &setup.user), Err(Ok(Error::NotAllowlisted)));
+    client.set_allowlist_enabled(&false);
+    client.redeem(&setup.user2, &300, &setup.user2, &setup.user2);
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+fn test_blocked_account_is_frozen_until_unblocked() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &500, &setup.user);
+    client.deposit(&setup.user2, &500, &setup.user2);
+    client.approve(&setup.user, &setup.user2, &500, &u32::MAX);
+
+    client.set_blocked(&setup.user, &true);
+    assert!(client.is_blocked(&setup.user));
+    assert!(!client.authorized(&setup.user));
+    assert_eq!(client.balance(&setup.user), 500);
+    assert_eq!(client.spendable_balance(&setup.user), 0);
+    assert_eq!(client.max_withdraw(&setup.user), 0);
+    assert_eq!(client.max_redeem(&setup.user), 0);
+    assert_eq!(client.max_deposit(&setup.user), 0);
+
+    // No moving shares in or out, directly or through an allowance
+    assert_eq!(client.try_transfer(&setup.user, &setup.user2, &100), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.try_transfer(&setup.user2, &setup.user, &100), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(
+        client.try_transfer_from(&setup.user2, &setup.user, &setup.user2, &100),
+        Err(Ok(Error::AddressBlocked))
+    );
+
+    // No exits, including by an approved caller or into an unblocked receiver
+    assert_eq!(client.try_withdraw(&setup.user, &100, &setup.user, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.try_redeem(&setup.user, &100, &setup.user, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.try_redeem(&setup.user2, &100, &setup.user2, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.try_redeem_all(&setup.user, &setup.user, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(
+        client.try_request_withdraw(&setup.user, &100, &setup.user, &setup.user),
+        Err(Ok(Error::AddressBlocked))
+    );
+
+    // No new shares either, even when someone else pays
+    assert_eq!(client.try_deposit(&setup.user2, &100, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.try_mint(&setup.user, &100, &setup.user), Err(Ok(Error::AddressBlocked)));
+    assert_eq!(client.balance_of(&setup.user), 500);
+
+    // Others are unaffected
+    client.redeem(&setup.user2, &100, &setup.user2, &setup.user2);
+
+    client.set_blocked(&setup.user, &false);
+    assert!(client.authorized(&setup.user));
+    client.transfer(&setup.user, &setup.user2, &100);
+    client.transfer_from(&setup.user2, &setup.user, &setup.user2, &100);
+    client.redeem(&setup.user, &300, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.user), 0);
+}
+
+#[test]
+fn test_balances_of() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &300, &setup.user);
+    client.deposit(&setup.user2, &700, &setup.user2);
+    let stranger = Address::generate(&setup.env);
+
+    assert_eq!(
+        client.balances_of(&vec![&setup.env, setup.user2.clone(), stranger.clone(), setup.user.clone()]),
+        vec![&setup.env, 700, 0, 300]
+    );
+    assert_eq!(client.balances_of(&Vec::new(&setup.env)), Vec::new(&setup.env));
+
+    let mut accounts = Vec::new(&setup.env);
+    for _ in 0..100 {
+        accounts.push_back(stranger.clone());
+    }
+    assert_eq!(client.balances_of(&accounts).len(), 100);
+    accounts.push_back(setup.user.clone());
+    assert_eq!(client.try_balances_of(&accounts), Err(Ok(Error::BatchTooLarge)));
+}
+
+#[test]
+fn test_holders_index() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.holders(&0, &10), vec![&setup.env]);
+
+    let user3 = Address::generate(&setup.env);
+    client.deposit(&setup.user, &300, &setup.user);
+    client.transfer(&setup.user, &setup.user2, &100);
+    client.transfer(&setup.user, &user3, &100);
+    assert_eq!(
+        client.holders(&0, &10),
+        vec![&setup.env, setup.user.clone(), setup.user2.clone(), user3.clone()]
+    );
+
+    // Pagination
+    assert_eq!(client.holders(&1, &1), vec![&setup.env, setup.user2.clone()]);
+    assert_eq!(client.holders(&2, &5), vec![&setup.env, user3.clone()]);
+    assert_eq!(client.holders(&3, &5), vec![&setup.env]);
+
+    // Emptying a balance removes the holder and moves the last one into its slot
+    client.redeem_all(&setup.user, &setup.user, &setup.user);
+    assert_eq!(client.holders(&0, &10), vec![&setup.env, user3.clone(), setup.user2.clone()]);
+
+    client.burn(&setup.user2, &100);
+    assert_eq!(client.holders(&0, &10), vec![&setup.env, user3.clone()]);
+
+    // Becoming a holder again appends
+    client.transfer(&user3, &setup.user, &50);
+    assert_eq!(client.holders(&0, &10), vec![&setup.env, user3.clone(), setup.user.clone()]);
+}
+
+#[test]
+fn test_total_holders() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.total_holders(), 0);
+
+    client.deposit(&setup.user, &100, &setup.user);
+    client.deposit(&setup.user, &100, &setup.user2);
+    assert_eq!(client.total_holders(), 2);
+
+    // Topping up an existing holder doesn't count twice
+    client.deposit(&setup.user, &100, &setup.user);
+    client.transfer(&setup.user, &setup.user2, &50);
+    assert_eq!(client.total_holders(), 2);
+
+    // A rejected self-transfer leaves the count alone
+    assert!(client.try_transfer(&setup.user, &setup.user, &10).is_err());
+    assert_eq!(client.total_holders(), 2);
+
+    client.transfer(&setup.user2, &setup.user, &150);
+    assert_eq!(client.total_holders(), 1);
+
+    client.redeem_all(&setup.user, &setup.user, &setup.user);
+    assert_eq!(client.total_holders(), 0);
+}
+
+#[test]
+fn test_min_deposit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_min_deposit(&100);
+    assert_eq!(client.min_deposit(), 100);
+
+    assert_eq!(
+        client.try_deposit(&setup.user, &99, &setup.user),
+        Err(Ok(Error::BelowMinimum))
+    );
+    assert_eq!(
+        client.try_mint(&setup.user, &99, &setup.user),
+        Err(Ok(Error::BelowMinimum))
+    );
+    let entries = vec![&setup.env, (100i128, setup.user.clone()), (50i128, setup.user2.clone())];
+    assert_eq!(
+        client.try_batch_deposit(&setup.user, &entries),
+        Err(Ok(Error::BelowMinimum))
+    );
+
+    assert_eq!(client.deposit(&setup.user, &100, &setup.user), 100);
+    assert_eq!(client.mint(&setup.user, &100, &setup.user), 100);
+}
+
+#[test]
+fn test_min_balance_forces_full_exit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+    client.set_min_balance(&20);
+
+    // Leaving 10 shares behind is dust
+    assert_eq!(
+        client.try_redeem(&setup.user, &90, &setup.user, &setup.user),
+        Err(Ok(Error::BelowMinimum))
+    );
+    assert_eq!(
+        client.try_withdraw(&setup.user, &90, &setup.user, &setup.user),
+        Err(Ok(Error::BelowMinimum))
+    );
+    assert_eq!(
+        client.try_request_withdraw(&setup.user, &90, &setup.user, &setup.user),
+        Err(Ok(Error::BelowMinimum))
+    );
+
+    // Leaving exactly the minimum, or nothing, is fine
+    client.redeem(&setup.user, &80, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.user), 20);
+    client.redeem_all(&setup.user, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.user), 0);
+}
+
+#[test]
+fn test_fees_collected() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.set_entry_fee(&setup.admin, &100);
+    client.set_exit_fee(&setup.admin, &200);
+    client.set_performance_fee(&setup.admin, &2_000);
+    assert_eq!(client.fees_collected(), (0, 0, 0));
+
+    let fee_event = |kind: &str, amount: i128| {
+        (
+            setup.vault_id.clone(),
+            (symbol_short!("v1"), Symbol::new(&setup.env, "fee_collected"), Symbol::new(&setup.env, kind)).into_val(&setup.env),
+            amount.into_val(&setup.env),
+        )
+    };
+
+    client.deposit(&setup.user, &1010, &setup.user);
+    assert!(setup.env.events().all().contains(fee_event("entry", 10)));
+    assert_eq!(client.fees_collected(), (10, 0, 0));
+
+    client.withdraw(&setup.user, &500, &setup.user, &setup.user);
+    assert!(setup.env.events().all().contains(fee_event("exit", 10)));
+    assert_eq!(client.fees_collected(), (10, 10, 0));
+
+    // No fee, no event
+    client.set_exit_fee(&setup.admin, &0);
+    client.redeem(&setup.user, &10, &setup.user, &setup.user);
+    assert!(!setup.env.events().all().contains(fee_event("exit", 0)));
+    assert_eq!(client.fees_collected(), (10, 10, 0));
+
+    // A gain above the high-water mark is charged and counted in assets
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+    setup.mint_tokens(&strategy_id, 100);
+    client.harvest();
+    let events = setup.env.events().all();
+    let (_, _, perf) = client.fees_collected();
+    assert_eq!(perf, 19);
+    assert!(events.contains(fee_event("perf", perf)));
+}
+
+#[test]
+fn test_deposit_from_balance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.try_deposit_from_balance(&setup.user), Err(Ok(Error::ZeroAssets)));
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.last_known_assets(), 1000);
+
+    // Sent in a prior step, claimed at the price before it arrived
+    token_client.transfer(&setup.user2, &setup.vault_id, &500);
+    assert_eq!(client.deposit_from_balance(&setup.user2), 500);
+    assert_eq!(client.balance_of(&setup.user2), 500);
+    assert_eq!(client.last_known_assets(), 1500);
+    assert_eq!(client.try_deposit_from_balance(&setup.user2), Err(Ok(Error::ZeroAssets)));
+
+    // Other paths fold unclaimed transfers into the share price and move the watermark
+    token_client.transfer(&setup.user2, &setup.vault_id, &100);
+    client.withdraw(&setup.user, &100, &setup.user, &setup.user);
+    assert_eq!(client.last_known_assets(), 1500);
+    assert_eq!(client.try_deposit_from_balance(&setup.user2), Err(Ok(Error::ZeroAssets)));
+}
+
+#[test]
+fn test_deposit_near_asset_ceiling() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    // Booked accounting close to the ceiling, which the token balance alone can't reach
+    client.set_deployed_assets(&(i128::MAX - 500));
+    assert_eq!(client.total_assets(), i128::MAX - 500);
+    assert_eq!(client.try_deposit(&setup.user, &1000, &setup.user), Err(Ok(Error::Overflow)));
+    assert_eq!(client.try_mint(&setup.user, &1, &setup.user), Err(Ok(Error::Overflow)));
+    assert_eq!(client.balance_of(&setup.user), 0);
+}
+
+#[test]
+fn test_donate() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    let price_before = client.price_per_share();
+
+    assert_eq!(client.try_donate(&setup.user2, &0), Err(Ok(Error::ZeroAssets)));
+    client.donate(&setup.user2, &500);
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        (symbol_short!("v1"), symbol_short!("donation"), setup.user2.clone()).into_val(&setup.env),
+        500i128.into_val(&setup.env),
+    )));
+
+    assert_eq!(token_client.balance(&setup.user2), 500);
+    assert_eq!(client.balance_of(&setup.user2), 0);
+    assert_eq!(client.total_supply(), 1000);
+    assert_eq!(client.total_assets(), 1500);
+    assert!(client.price_per_share() > price_before);
+    assert_eq!(client.convert_to_assets(&1000), 1499);
+
+    // Booked as known assets, so nobody can mint shares against it
+    assert_eq!(client.try_deposit_from_balance(&setup.user2), Err(Ok(Error::ZeroAssets)));
+}
+
+#[test]
+fn test_balance_snapshots() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    setup.env.ledger().set_sequence_number(100);
+    client.deposit(&setup.user, &500, &setup.user);
+
+    setup.env.ledger().set_sequence_number(110);
+    client.transfer(&setup.user, &setup.user2, &200);
+    // Only the last change in a ledger is kept
+    client.transfer(&setup.user, &setup.user2, &100);
+
+    setup.env.ledger().set_sequence_number(120);
+    client.redeem(&setup.user, &200, &setup.user, &setup.user);
+
+    for (ledger, user, user2, supply) in [
+        (99, 0, 0, 0),
+        (100, 500, 0, 500),
+        (105, 500, 0, 500),
+        (110, 200, 300, 500),
+        (119, 200, 300, 500),
+        (120, 0, 300, 300),
+        (1_000, 0, 300, 300),
+    ] {
+        assert_eq!(client.balance_of_at(&setup.user, &ledger), user);
+        assert_eq!(client.balance_of_at(&setup.user2, &ledger), user2);
+        assert_eq!(client.total_supply_at(&ledger), supply);
+    }
+}
+
+#[test]
+fn test_flash_loan_repaid() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.set_flash_fee(&setup.admin, &50);
+
+    let borrower = setup.env.register(flash_borrower::MockBorrower, ());
+    flash_borrower::MockBorrowerClient::new(&setup.env, &borrower).initialize(&setup.vault_id, &true);
+    setup.mint_tokens(&borrower, 10);
+
+    // 0.5% of 1000, rounded up, stays with the vault
+    assert_eq!(client.flash_loan(&borrower, &1000, &Bytes::new(&setup.env)), 5);
+    assert_eq!(token_client.balance(&borrower), 5);
+    assert_eq!(client.total_assets(), 1005);
+    assert_eq!(client.last_known_assets(), 1005);
+    assert_eq!(client.convert_to_assets(&1000), 1004);
+
+    assert_eq!(
+        client.try_flash_loan(&borrower, &1006, &Bytes::new(&setup.env)),
+        Err(Ok(Error::InsufficientIdleAssets))
+    );
+}
+
+#[test]
+fn test_flash_loan_not_repaid() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.set_flash_fee(&setup.admin, &50);
+
+    // Returns the principal but not the fee
+    let borrower = setup.env.register(flash_borrower::MockBorrower, ());
+    flash_borrower::MockBorrowerClient::new(&setup.env, &borrower).initialize(&setup.vault_id, &false);
+
+    assert_eq!(
+        client.try_flash_loan(&borrower, &1000, &Bytes::new(&setup.env)),
+        Err(Ok(Error::FlashLoanNotRepaid))
+    );
+    assert_eq!(token_client.balance(&setup.vault_id), 1000);
+    assert_eq!(token_client.balance(&borrower), 0);
+}
+
+#[test]
+fn test_deposit_from_payer() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let treasury = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_fee_recipient(&setup.admin, &treasury);
+    client.set_entry_fee(&setup.admin, &100);
+
+    // Without an allowance on the asset the vault can't pull anything
+    assert!(client.try_deposit_from(&setup.user, &505, &setup.user2).is_err());
+
+    token_client.approve(&setup.user, &setup.vault_id, &505, &1000);
+    assert_eq!(client.deposit_from(&setup.user, &505, &setup.user2), 500);
+    assert_eq!(setup.env.auths()[0].0, setup.user);
+    assert_eq!(token_client.balance(&setup.user), 495);
+    assert_eq!(token_client.balance(&treasury), 5);
+    assert_eq!(token_client.allowance(&setup.user, &setup.vault_id), 0);
+    assert_eq!(client.balance_of(&setup.user2), 500);
+    assert_eq!(client.balance_of(&setup.user), 0);
+}
+
+#[test]
+fn test_balances_expire_with_instance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    let ttl = || setup.env.as_contract(&setup.vault_id, || setup.env.storage().instance().get_ttl());
+    let before = ttl();
+    setup.env.ledger().with_mut(|li| li.sequence_number += 100);
+    assert_eq!(ttl(), before - 100);
+    assert_eq!(client.balance_of(&setup.user), 1000);
+}
+
+#[test]
+fn test_sep41_token_client() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    // Shares through the standard token client
+    let shares = soroban_sdk::token::Client::new(&setup.env, &setup.vault_id);
+    assert_eq!(shares.decimals(), 18);
+    assert_eq!(shares.name(), String::from_str(&setup.env, "Test Vault"));
+    assert_eq!(shares.symbol(), String::from_str(&setup.env, "TVAULT"));
+    assert_eq!(shares.balance(&setup.user), 1000);
+
+    shares.transfer(&setup.user, &setup.user2, &100);
+    assert_eq!(shares.balance(&setup.user2), 100);
+
+    shares.approve(&setup.user, &setup.user2, &200, &(setup.env.ledger().sequence() + 100));
+    assert_eq!(shares.allowance(&setup.user, &setup.user2), 200);
+    shares.transfer_from(&setup.user2, &setup.user, &setup.user2, &150);
+    assert_eq!(shares.allowance(&setup.user, &setup.user2), 50);
+    assert_eq!(shares.balance(&setup.user), 750);
+
+    shares.burn(&setup.user2, &50);
+    assert_eq!(shares.balance(&setup.user2), 200);
+
+    let admin_client = soroban_sdk::token::StellarAssetClient::new(&setup.env, &setup.vault_id);
+    assert!(admin_client.authorized(&setup.user));
+    assert_eq!(client.spendable_balance(&setup.user), 750);
+}
+
+#[test]
+fn test_backing_report() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    let strategy_id = setup.env.register(strategy::MockStrategy, ());
+    strategy::MockStrategyClient::new(&setup.env, &strategy_id).initialize(&setup.token_id, &setup.vault_id);
+    client.set_strategy(&strategy_id);
+    client.deposit(&setup.user, &1000, &setup.user);
+    client.deploy_to_strategy(&600);
+
+    let report = client.backing_report();
+    assert_eq!(report.total_assets, 1000);
+    assert_eq!(report.total_supply, 1000);
+    assert_eq!(report.implied_assets, 1000);
+    assert_eq!(report.gap, 0);
+
+    // The strategy loses 200 that hasn't been harvested yet
+    token_client.transfer(&strategy_id, &setup.user2, &200);
+    let report = client.backing_report();
+    assert_eq!(report.total_assets, 800);
+    assert_eq!(report.implied_assets, 1000);
+    assert_eq!(report.gap, -200);
+
+    // Harvesting books the loss and closes the gap
+    client.harvest();
+    let report = client.backing_report();
+    assert_eq!(report.total_assets, 800);
+    assert_eq!(report.gap, 0);
+}
+
+#[test]
+fn test_migrate_runs_once() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.version(), 2);
+    assert_eq!(client.try_migrate(&2), Err(Ok(Error::AlreadyMigrated)));
+
+    client.deposit(&setup.user, &100, &setup.user);
+
+    // Roll the vault back to the layout written before versioning
+    setup.env.as_contract(&setup.vault_id, || {
+        setup.env.storage().instance().remove(&UpgradeKey::Version);
+        setup.env.storage().instance().remove(&AccountingKey::LastKnownAssets);
+        setup.env.storage().persistent().remove(&AccountingKey::SupplyCheckpointCount);
+    });
+    setup.env.ledger().with_mut(|li| li.sequence_number += 10);
+    let ledger = setup.env.ledger().sequence();
+    assert_eq!(client.version(), 0);
+    assert_eq!(client.total_supply_at(&ledger), 0);
+
+    assert_eq!(client.try_migrate(&3), Err(Ok(Error::InvalidVersion)));
+    client.migrate(&1);
+    assert_eq!(client.version(), 1);
+    assert_eq!(client.total_supply_at(&ledger), 100);
+    assert_eq!(client.last_known_assets(), 100);
+
+    assert_eq!(client.try_migrate(&1), Err(Ok(Error::AlreadyMigrated)));
+    assert_eq!(client.version(), 1);
+}
+
+#[test]
+fn test_config_round_trip() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    assert_eq!(client.try_config(), Err(Ok(Error::NotInitialized)));
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", 7, 3);
+    setup.env.mock_all_auths();
+    assert_eq!(
+        client.config(),
+        Config {
+            admin: setup.admin.clone(),
+            asset: setup.token_id.clone(),
+            name: String::from_str(&setup.env, "Test Vault"),
+            symbol: String::from_str(&setup.env, "TVAULT"),
+            decimals: 7,
+            decimals_offset: 3,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+        }
+    );
+
+    client.set_entry_fee(&setup.admin, &50);
+    client.set_exit_fee(&setup.admin, &25);
+    client.set_metadata(&String::from_str(&setup.env, "Renamed"), &String::from_str(&setup.env, "RNM"));
+    client.set_admin(&setup.user);
+    client.accept_admin();
+
+    let config = client.config();
+    assert_eq!(config.admin, client.admin());
+    assert_eq!(config.admin, setup.user);
+    assert_eq!(config.asset, client.asset());
+    assert_eq!(config.name, client.name());
+    assert_eq!(config.name, String::from_str(&setup.env, "Renamed"));
+    assert_eq!(config.symbol, client.symbol());
+    assert_eq!(config.symbol, String::from_str(&setup.env, "RNM"));
+    assert_eq!(config.decimals, client.asset_decimals());
+    assert_eq!(config.decimals_offset, client.decimals_offset());
+    assert_eq!(client.decimals(), 10);
+    assert_eq!(config.entry_fee_bps, client.entry_fee_bps());
+    assert_eq!(config.entry_fee_bps, 50);
+    assert_eq!(config.exit_fee_bps, client.exit_fee_bps());
+    assert_eq!(config.exit_fee_bps, 25);
+}
+
+#[test]
+fn test_migrate_packs_config() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.env.mock_all_auths();
+    client.set_entry_fee(&setup.admin, &50);
+    let config = client.config();
+
+    // Roll the vault back to the layout with a key per setting
+    setup.env.as_contract(&setup.vault_id, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&ConfigKey::Config);
+        storage.set(&DataKey::Admin, &config.admin);
+        storage.set(&DataKey::Asset, &config.asset);
+        storage.set(&DataKey::Name, &config.name);
+        storage.set(&DataKey::Symbol, &config.symbol);
+        storage.set(&DataKey::Decimals, &config.decimals);
+        storage.set(&DataKey::EntryFeeBps, &config.entry_fee_bps);
+        storage.set(&UpgradeKey::Version, &1u32);
+    });
+    assert_eq!(client.config(), config);
+
+    client.migrate(&2);
+    assert_eq!(client.version(), 2);
+    assert_eq!(client.config(), config);
+    setup.env.as_contract(&setup.vault_id, || {
+        let storage = setup.env.storage().instance();
+        assert!(storage.has(&ConfigKey::Config));
+        assert!(!storage.has(&DataKey::Admin));
+        assert!(!storage.has(&DataKey::EntryFeeBps));
+    });
+
+    // Settings changed between the upgrade and the migration survive it
+    setup.env.as_contract(&setup.vault_id, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&ConfigKey::Config);
+        storage.set(&DataKey::Admin, &config.admin);
+        storage.set(&DataKey::Asset, &config.asset);
+        storage.set(&DataKey::Name, &config.name);
+        storage.set(&DataKey::Symbol, &config.symbol);
+        storage.set(&DataKey::Decimals, &config.decimals);
+        storage.set(&DataKey::EntryFeeBps, &config.entry_fee_bps);
+        storage.set(&UpgradeKey::Version, &1u32);
+    });
+    client.set_entry_fee(&setup.admin, &75);
+    client.propose_admin(&setup.user);
+    client.accept_admin();
+    let updated = client.config();
+    assert_eq!(updated.entry_fee_bps, 75);
+    assert_eq!(updated.admin, setup.user);
+
+    client.migrate(&2);
+    assert_eq!(client.config(), updated);
+    setup.env.as_contract(&setup.vault_id, || {
+        let storage = setup.env.storage().instance();
+        assert!(!storage.has(&DataKey::Admin));
+        assert!(!storage.has(&DataKey::EntryFeeBps));
+    });
+}
+
+#[test]
+fn test_last_shares_cannot_be_burned_with_dead_shares() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    // Enabled after the first deposit, so no dead shares were minted
+    client.deposit(&setup.user, &500, &setup.user);
+    client.set_dead_shares(&true);
+
+    assert_eq!(client.try_redeem(&setup.user, &500, &setup.user, &setup.user), Err(Ok(Error::WouldEmptyVault)));
+    assert_eq!(client.try_withdraw(&setup.user, &500, &setup.user, &setup.user), Err(Ok(Error::WouldEmptyVault)));
+    assert_eq!(client.try_burn(&setup.user, &500), Err(Ok(Error::WouldEmptyVault)));
+    assert_eq!(
+        client.try_request_withdraw(&setup.user, &500, &setup.user, &setup.user),
+        Err(Ok(Error::WouldEmptyVault))
+    );
+    client.redeem(&setup.user, &499, &setup.user, &setup.user);
+    assert_eq!(client.total_supply(), 1);
+
+    // Another holder keeps the supply up, so the first can leave
+    client.deposit(&setup.user2, &100, &setup.user2);
+    client.redeem(&setup.user, &1, &setup.user, &setup.user);
+    assert_eq!(client.try_redeem_all(&setup.user2, &setup.user2, &setup.user2), Err(Ok(Error::WouldEmptyVault)));
+
+    // Shutdown lets the last holder out
+    client.shutdown();
+    client.redeem_all(&setup.user2, &setup.user2, &setup.user2);
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+fn test_seeded_vault_keeps_dead_shares_after_full_exit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.initialize_with_deposit(
+        &setup.admin,
+        &setup.token_id,
+        &String::from_str(&setup.env, "Test Vault"),
+        &String::from_str(&setup.env, "TVAULT"),
+        &18,
+        &setup.user,
+        &5_000,
+    );
+    client.redeem_all(&setup.user, &setup.user, &setup.user);
+    assert_eq!(client.balance_of(&setup.user), 0);
+    assert_eq!(client.total_supply(), 1_000);
+}
+
+#[test]
+fn test_price_smoothing_damps_donation_spike() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+    setup.mint_tokens(&setup.user2, 10_000);
+
+    setup.env.mock_all_auths();
+    client.set_price_smoothing(&1_000);
+    client.deposit(&setup.user, &1000, &setup.user);
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.deposit(&setup.user2, &100, &setup.user2);
+    let price = client.price_per_share();
+    assert_eq!(client.convert_to_assets(&1000), 1000);
+
+    // Doubling the balance within the ledger moves nothing but the raw figure
+    token_client.transfer(&setup.user, &setup.vault_id, &1100);
+    assert_eq!(client.total_assets(), 2200);
+    assert_eq!(client.smoothed_assets(), 1100);
+    assert_eq!(client.price_per_share(), price);
+    assert_eq!(client.convert_to_assets(&1000), 1000);
+    client.deposit(&setup.user2, &100, &setup.user2);
+    assert_eq!(client.price_per_share(), price);
+
+    // Each later ledger closes 10% of the gap
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.deposit(&setup.user2, &100, &setup.user2);
+    let stepped = client.price_per_share();
+    assert!(stepped > price);
+    assert!(stepped < price + price / 10);
+    assert!(client.smoothed_assets() < client.total_assets());
+
+    // Turning it off prices against the live balance again
+    client.set_price_smoothing(&0);
+    assert_eq!(client.smoothed_assets(), client.total_assets());
+    assert!(client.price_per_share() > price + price / 2);
+    assert_eq!(client.try_set_price_smoothing(&10_001), Err(Ok(Error::InvalidFee)));
+}
+
+#[test]
+fn test_convert_to_shares_detailed_uses_smoothed_price() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 0);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 10_000);
+
+    setup.env.mock_all_auths();
+    client.set_price_smoothing(&1_000);
+    client.deposit(&setup.user, &1000, &setup.user);
+    setup.env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.deposit(&setup.user, &100, &setup.user);
+
+    // The donation doubles the raw balance but not the smoothed price both views quote
+    token_client.transfer(&setup.user, &setup.vault_id, &1100);
+    assert_eq!(client.convert_to_shares(&100), 100);
+    assert_eq!(client.convert_to_shares_detailed(&100), (100, 0));
+}
+
+#[test]
+fn test_negative_amounts_rejected() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &500, &setup.user);
+    client.deposit(&setup.user2, &500, &setup.user2);
+    client.approve(&setup.user2, &setup.user, &100, &u32::MAX);
+
+    // Without the check these would pull shares from `to`, raise the allowance or mint
+    assert_eq!(client.try_transfer(&setup.user, &setup.user2, &-100), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(
+        client.try_transfer_from(&setup.user, &setup.user2, &setup.user, &-100),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(client.try_burn(&setup.user, &-100), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_approve(&setup.user, &setup.user2, &-1, &u32::MAX), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(
+        client.try_approve_with_expiry(&setup.user, &setup.user2, &-1, &u32::MAX),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(client.try_increase_allowance(&setup.user2, &setup.user, &-50), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_decrease_allowance(&setup.user2, &setup.user, &-50), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(
+        client.try_transfer_checked(&setup.user, &setup.user2, &-100, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    assert_eq!(client.try_deposit(&setup.user, &-100, &setup.user), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_mint(&setup.user, &-100, &setup.user), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_withdraw(&setup.user, &-100, &setup.user, &setup.user), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_redeem(&setup.user, &-100, &setup.user, &setup.user), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(
+        client.try_request_withdraw(&setup.user, &-100, &setup.user, &setup.user),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_batch_deposit(&setup.user, &vec![&setup.env, (-100, setup.user.clone())]),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    client.set_strategy(&Address::generate(&setup.env));
+    assert_eq!(client.try_deploy_to_strategy(&-100), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.try_set_deployed_assets(&-100), Err(Ok(Error::InvalidAmount)));
+    assert_eq!(
+        client.try_flash_loan(&setup.user, &-100, &Bytes::new(&setup.env)),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    assert_eq!(client.balance_of(&setup.user), 500);
+    assert_eq!(client.balance_of(&setup.user2), 500);
+    assert_eq!(client.total_supply(), 1000);
+    assert_eq!(client.total_assets(), 1000);
+    assert_eq!(client.allowance(&setup.user2, &setup.user), 100);
+    assert_eq!(client.allowance(&setup.user, &setup.user2), 0);
+
+    // Zero keeps its existing meaning
+    assert_eq!(client.try_deposit(&setup.user, &0, &setup.user), Err(Ok(Error::ZeroAssets)));
+    assert_eq!(client.try_redeem(&setup.user, &0, &setup.user, &setup.user), Err(Ok(Error::ZeroShares)));
+    client.approve(&setup.user2, &setup.user, &0, &u32::MAX);
+    assert_eq!(client.allowance(&setup.user2, &setup.user), 0);
+}
+
+#[test]
+fn test_rebase_goes_to_existing_holders() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 2000);
+
+    setup.env.mock_all_auths();
+    client.set_rebasing(&true);
+    client.deposit(&setup.user, &1000, &setup.user);
+    assert_eq!(client.rebase_checkpoint(), None);
+
+    // +10% lands in the vault's balance without any call
+    token_client.rebase(&setup.vault_id, &1_000);
+    assert_eq!(client.total_assets(), 1100);
+
+    // Claiming it as a direct transfer finds nothing once the rebase is booked
+    assert_eq!(client.try_deposit_from_balance(&setup.user2), Err(Ok(Error::ZeroAssets)));
+
+    // The next entry books it for the current holders and the newcomer pays the new price
+    let shares = client.deposit(&setup.user2, &1100, &setup.user2);
+    assert!(setup.env.events().all().contains((
+        setup.vault_id.clone(),
+        (symbol_short!("v1"), symbol_short!("rebase")).into_val(&setup.env),
+        (100i128, 1100i128).into_val(&setup.env),
+    )));
+    assert_eq!(shares, 1000);
+    assert_eq!(
+        client.rebase_checkpoint(),
+        Some(Snapshot { ledger: setup.env.ledger().sequence(), value: 1100 })
+    );
+    assert_eq!(client.last_known_assets(), 2200);
+    assert_eq!(client.try_deposit_from_balance(&setup.user2), Err(Ok(Error::ZeroAssets)));
+    assert_eq!(client.preview_redeem(&1000), 1099);
+    assert_eq!(client.preview_redeem(&shares), 1099);
+}
+
+#[test]
+fn test_upgrade_preserves_state() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &100, &setup.user);
+
+    let new_wasm_hash = setup.env.deployer().upload_contract_wasm(minimal_wasm(&setup.env));
+    client.upgrade(&new_wasm_hash);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+
+    let (_, _, data) = setup.env.events().all().last().unwrap();
+    let event_hash: BytesN<32> = data.into_val(&setup.env);
+    assert_eq!(event_hash, new_wasm_hash);
+
+    setup.env.as_contract(&setup.vault_id, || {
+        let balance: i128 = setup.env.storage().instance().get(&DataKey::Balance(setup.user.clone())).unwrap();
+        let total_supply: i128 = setup.env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        assert_eq!(balance, 100);
+        assert_eq!(total_supply, 100);
+    });
+}
+
+#[test]
+#[should_panic]
+fn test_upgrade_requires_admin() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let new_wasm_hash = setup.env.deployer().upload_contract_wasm(minimal_wasm(&setup.env));
+    client.upgrade(&new_wasm_hash);
+}
+
+#[test]
+fn test_reentrant_withdraw_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let vault_id = env.register(VaultContract, ());
+    let token_id = env.register(reentrant_token::ReentrantToken, ());
+    let admin = Address::generate(&env);
+    let attacker = Address::generate(&env);
+
+    let client = VaultContractClient::new(&env, &vault_id);
+    let token_client = reentrant_token::ReentrantTokenClient::new(&env, &token_id);
+    client.initialize(
+        &admin,
+        &token_id,
+        &String::from_str(&env, "Test Vault"),
+        &String::from_str(&env, "TVAULT"),
+        &18,
+        &0,
+        &None,
+    );
+
+    token_client.mint(&attacker, &1000);
+    client.deposit(&attacker, &200, &attacker);
+    token_client.set_target(&vault_id, &attacker);
+
+    client.withdraw(&attacker, &50, &attacker, &attacker);
+
+    assert!(token_client.reentry_failed());
+    assert_eq!(client.balance_of(&attacker), 150);
+    assert_eq!(client.total_supply(), 150);
+    assert_eq!(client.total_assets(), 150);
+    assert_eq!(token_client.balance(&attacker), 850);
+}
+
+#[test]
+fn test_reentrancy_guard() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &200, &setup.user);
+
+    setup.env.as_contract(&setup.vault_id, || {
+        setup.env.storage().temporary().set(&DataKey::ReentrancyGuard, &true);
+    });
+    assert_eq!(
+        client.try_withdraw(&setup.user, &50, &setup.user, &setup.user),
+        Err(Ok(Error::Reentrancy))
+    );
+    assert_eq!(
+        client.try_deposit(&setup.user, &50, &setup.user),
+        Err(Ok(Error::Reentrancy))
+    );
+
+    setup.env.as_contract(&setup.vault_id, || {
+        setup.env.storage().temporary().remove(&DataKey::ReentrancyGuard);
+    });
+
+    // The guard is released after both successful and failing calls
+    assert_eq!(
+        client.try_redeem(&setup.user2, &10, &setup.user2, &setup.user),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    assert_eq!(client.withdraw(&setup.user, &50, &setup.user, &setup.user), 50);
+    assert_eq!(client.deposit(&setup.user, &50, &setup.user), 50);
+}
+
+#[test]
+fn test_withdraw_insufficient_vault_assets() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &200, &setup.user);
+
+    // Underlying balance drops out from under the vault
+    token_client.transfer(&setup.vault_id, &setup.user2, &190);
+
+    assert_eq!(
+        client.try_withdraw(&setup.user, &50, &setup.user, &setup.user),
+        Err(Ok(Error::InsufficientVaultAssets))
+    );
+    assert_eq!(client.balance_of(&setup.user), 200);
+    assert_eq!(client.total_supply(), 200);
+    assert_eq!(client.total_assets(), 10);
+}
+
+#[test]
+fn test_batch_deposit() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let user3 = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    let entries = vec![
+        &setup.env,
+        (100i128, setup.user.clone()),
+        (200i128, setup.user2.clone()),
+        (300i128, user3.clone()),
+    ];
+    let minted = client.batch_deposit(&setup.user, &entries);
+    assert_eq!(minted, vec![&setup.env, 100i128, 200, 300]);
+
+    assert_eq!(client.balance_of(&setup.user), 100);
+    assert_eq!(client.balance_of(&setup.user2), 200);
+    assert_eq!(client.balance_of(&user3), 300);
+    assert_eq!(client.total_supply(), 600);
+    assert_eq!(token_client.balance(&setup.user), 400);
+    assert_eq!(client.total_assets(), 600);
+}
+
+#[test]
+fn test_batch_deposit_empty() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+
+    setup.env.mock_all_auths();
+
+    let minted = client.batch_deposit(&setup.user, &Vec::new(&setup.env));
+    assert!(minted.is_empty());
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+fn test_batch_deposit_rejects_zero_entry() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+
+    let entries = vec![&setup.env, (100i128, setup.user.clone()), (0i128, setup.user2.clone())];
+    assert_eq!(client.try_batch_deposit(&setup.user, &entries), Err(Ok(Error::ZeroAssets)));
+    assert_eq!(client.total_supply(), 0);
+}
+
+#[test]
+fn test_batch_redeem() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let relayer = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &500, &setup.user);
+    client.deposit(&setup.user2, &300, &setup.user2);
+    client.approve(&setup.user, &relayer, &200, &u32::MAX);
+    client.approve(&setup.user2, &relayer, &300, &u32::MAX);
+
+    let entries = vec![
+        &setup.env,
+        (200i128, setup.user.clone(), setup.user.clone()),
+        (300i128, setup.user.clone(), setup.user2.clone()),
+    ];
+    assert_eq!(client.batch_redeem(&relayer, &entries), vec![&setup.env, 200i128, 300]);
+    assert_eq!(client.balance_of(&setup.user), 300);
+    assert_eq!(client.balance_of(&setup.user2), 0);
+    assert_eq!(token_client.balance(&setup.user), 1000);
+    assert_eq!(client.allowance(&setup.user, &relayer), 0);
+    assert_eq!(client.allowance(&setup.user2, &relayer), 0);
+}
+
+#[test]
+fn test_batch_redeem_reverts_on_missing_allowance() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+    let relayer = Address::generate(&setup.env);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+    setup.mint_tokens(&setup.user2, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &500, &setup.user);
+    client.deposit(&setup.user2, &300, &setup.user2);
+    client.approve(&setup.user, &relayer, &200, &u32::MAX);
+
+    // The second owner never approved the relayer, so the first redemption is undone too
+    let entries = vec![
+        &setup.env,
+        (200i128, setup.user.clone(), setup.user.clone()),
+        (300i128, setup.user2.clone(), setup.user2.clone()),
+    ];
+    assert_eq!(client.try_batch_redeem(&relayer, &entries), Err(Ok(Error::InsufficientAllowance)));
+    assert_eq!(client.balance_of(&setup.user), 500);
+    assert_eq!(client.balance_of(&setup.user2), 300);
+    assert_eq!(client.allowance(&setup.user, &relayer), 200);
+    assert_eq!(token_client.balance(&setup.user), 500);
+    assert_eq!(client.total_assets(), 800);
+}
+
+#[test]
+fn test_deposit_fee_on_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let vault_id = env.register(VaultContract, ());
+    let token_id = env.register(fee_token::FeeOnTransferToken, ());
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let client = VaultContractClient::new(&env, &vault_id);
+    let token_client = fee_token::FeeOnTransferTokenClient::new(&env, &token_id);
+    client.initialize(
+        &admin,
+        &token_id,
+        &String::from_str(&env, "Test Vault"),
+        &String::from_str(&env, "TVAULT"),
+        &18,
+        &0,
+        &None,
+    );
+
+    token_client.set_fee_bps(&1000);
+    token_client.mint(&user, &1000);
+    token_client.mint(&user2, &1000);
+
+    // 10% of each transfer is skimmed, so only 90 of 100 reaches the vault
+    assert_eq!(client.preview_deposit(&100), 100);
+    assert_eq!(client.deposit(&user, &100, &user), 90);
+    assert_eq!(client.total_assets(), 90);
+    assert_eq!(client.deposit(&user2, &100, &user2), 90);
+    assert_eq!(client.total_assets(), 180);
+    assert_eq!(client.total_supply(), 180);
+}
+
+#[test]
+fn test_split_amount() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 7);
+
+    assert_eq!(client.split_amount(&0), (0, 0));
+    assert_eq!(client.split_amount(&10_000_000), (1, 0));
+    assert_eq!(client.split_amount(&125_000_000), (12, 5_000_000));
+    assert_eq!(client.split_amount(&1), (0, 1));
+    assert_eq!(client.split_amount(&9_999_999), (0, 9_999_999));
+    assert_eq!(client.split_amount(&-15_000_000), (-1, -5_000_000));
+    assert_eq!(client.split_amount(&-1), (0, -1));
+}
+
+#[test]
+fn test_exchange_rate() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    assert_eq!(client.exchange_rate(), (1, 1));
+
+    client.deposit(&setup.user, &400, &setup.user);
+    setup.mint_tokens(&setup.vault_id, 50);
+    assert_eq!(client.exchange_rate(), (450, 400));
+    assert_eq!(client.exchange_rate(), (client.total_assets(), client.total_supply()));
+}
+
+#[test]
+fn test_status() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.set_deposit_cap(&1000);
+    client.deposit(&setup.user, &900, &setup.user);
+    assert_eq!(
+        client.status(),
+        VaultStatus {
+            paused: false,
+            shutdown: false,
+            solvent: true,
+            deposit_room: 100,
+            idle_assets: 900,
+        }
+    );
+
+    client.pause(&setup.admin);
+    client.set_min_solvency_bps(&9_500);
+    token_client.transfer(&setup.vault_id, &setup.user2, &100);
+    let status = client.status();
+    assert!(status.paused);
+    assert!(!status.solvent);
+    assert_eq!(status.deposit_room, 200);
+    assert_eq!(status.idle_assets, 800);
+
+    client.shutdown();
+    assert!(client.status().shutdown);
+}
+
+#[test]
+fn test_vault_info() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 2);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &300, &setup.user);
+    token_client.transfer(&setup.user, &setup.vault_id, &150);
+
+    let info = client.vault_info();
+    assert_eq!(info.name, client.name());
+    assert_eq!(info.symbol, client.symbol());
+    assert_eq!(info.decimals, client.decimals());
+    assert_eq!(info.asset, client.asset());
+    assert_eq!(info.total_supply, client.total_supply());
+    assert_eq!(info.total_assets, client.total_assets());
+    assert_eq!(info.price_per_share, client.convert_to_assets(&100));
+    assert_eq!(info.price_per_share, 149);
+}
+
+#[test]
+fn test_price_per_share() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+    setup.initialize_vault("Test Vault", "TVAULT", 2);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    assert_eq!(client.price_per_share(), 100);
+    assert_eq!(client.shares_per_asset(), 100);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &300, &setup.user);
+    assert_eq!(client.price_per_share(), 100);
+    assert_eq!(client.shares_per_asset(), 100);
+
+    // 150 of accrued assets: 100 * 451 / 301 and 100 * 301 / 451
+    token_client.transfer(&setup.user, &setup.vault_id, &150);
+    assert_eq!(client.price_per_share(), 149);
+    assert_eq!(client.shares_per_asset(), 66);
+}
+
+#[test]
+fn test_price_per_share_with_offset() {
+    let setup = TestSetup::new();
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+    setup.initialize_vault_with_offset("Test Vault", "TVAULT", 2, 3);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    assert_eq!(client.price_per_share(), 100);
+    assert_eq!(client.shares_per_asset(), 100_000);
+
+    setup.env.mock_all_auths();
+    client.deposit(&setup.user, &300, &setup.user);
+    assert_eq!(client.price_per_share(), 100);
+    assert_eq!(client.shares_per_asset(), 100_000);
+}
+
+#[test]
+fn test_effects_ordered_around_token_calls() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let vault_id = env.register(VaultContract, ());
+    let token_id = env.register(logging_token::LoggingToken, ());
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let client = VaultContractClient::new(&env, &vault_id);
+    let token_client = logging_token::LoggingTokenClient::new(&env, &token_id);
+    client.initialize(
+        &admin,
+        &token_id,
+        &String::from_str(&env, "Test Vault"),
+        &String::from_str(&env, "TVAULT"),
+        &18,
+        &0,
+        &None,
+    );
+    client.set_deposit_cap(&1_000_000);
+    token_client.mint(&user, &10_000);
+
+    // Position of the first event from `contract` whose first topic is `name`
+    let position = |contract: &Address, name: &str| -> usize {
+        let events = env.events().all();
+        let name = Symbol::new(&env, name);
+        (0..events.len())
+            .find(|&i| {
+                let (id, topics, _) = events.get(i).unwrap();
+                // The vault's events lead with the version topic
+                let index = if id == vault_id { 1 } else { 0 };
+                let topic: Symbol = topics.get(index).unwrap().into_val(&env);
+                id == *contract && topic == name
+            })
+            .unwrap() as usize
+    };
+
+    // Deposits pull assets before minting
+    client.deposit(&user, &1000, &user);
+    assert!(position(&token_id, "xfer") < position(&vault_id, "mint"));
+
+    client.mint(&user, &100, &user);
+    assert!(position(&token_id, "xfer") < position(&vault_id, "mint"));
+
+    // Exits burn before paying out
+    client.withdraw(&user, &100, &user, &user);
+    assert!(position(&vault_id, "burn") < position(&token_id, "xfer"));
+
+    client.redeem(&user, &100, &user, &user);
+    assert!(position(&vault_id, "burn") < position(&token_id, "xfer"));
+}
+
+#[test]
+fn test_total_assets_read_once_per_call() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let vault_id = env.register(VaultContract, ());
+    let token_id = env.register(counting_token::CountingToken, ());
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let client = VaultContractClient::new(&env, &vault_id);
+    let token_client = counting_token::CountingTokenClient::new(&env, &token_id);
+    client.initialize(
+        &admin,
+        &token_id,
+        &String::from_str(&env, "Test Vault"),
+        &String::from_str(&env, "TVAULT"),
+        &18,
+        &0,
+        &None,
+    );
+    client.set_entry_fee(&admin, &100);
+    client.set_exit_fee(&admin, &100);
+    client.set_deposit_cap(&1_000_000);
+    token_client.mint(&user, &10_000);
+
+    // Deposit measures what arrived, so it reads the balance before and after the transfer
+    token_client.reset();
+    client.deposit(&user, &1000, &user);
+    assert_eq!(token_client.balance_calls(), 2);
+
+    token_client.reset();
+    client.mint(&user, &100, &user);
+    assert!(token_client.balance_calls() <= 1);
+
+    token_client.reset();
+    client.withdraw(&user, &100, &user, &user);
+    assert!(token_client.balance_calls() <= 1);
+
+    token_client.reset();
+    client.redeem(&user, &100, &user, &user);
+    assert!(token_client.balance_calls() <= 1);
+
+    token_client.reset();
+    client.vault_info();
+    assert!(token_client.balance_calls() <= 1);
+
+    token_client.reset();
+    client.max_mint(&user);
+    assert!(token_client.balance_calls() <= 1);
+
+    token_client.reset();
+    client.preview_withdraw(&100);
+    assert!(token_client.balance_calls() <= 1);
+
+    assert_eq!(client.status().idle_assets, client.idle_assets());
+    token_client.reset();
+    client.status();
+    assert!(token_client.balance_calls() <= 1);
+}
+
+#[test]
+fn test_sweep_foreign_token() {
+    let setup = TestSetup::new();
+    setup.env.mock_all_auths();
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+
+    let foreign_id = setup.env.register(token::MockToken, ());
+    let foreign = token::MockTokenClient::new(&setup.env, &foreign_id);
+    foreign.mint(&setup.vault_id, &500);
+
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    client.sweep(&foreign_id, &setup.user2, &300);
+
+    assert_eq!(foreign.balance(&setup.vault_id), 200);
+    assert_eq!(foreign.balance(&setup.user2), 300);
+}
+
+#[test]
+fn test_sweep_vault_asset_rejected() {
+    let setup = TestSetup::new();
+    setup.env.mock_all_auths();
+    setup.initialize_vault("Test Vault", "TVAULT", 18);
+    setup.initialize_token(1_000_000);
+    setup.mint_tokens(&setup.user, 1000);
+
+    let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+    client.deposit(&setup.user, &1000, &setup.user);
+
+    assert_eq!(
+        client.try_sweep(&setup.token_id, &setup.admin, &1000),
+        Err(Ok(Error::CannotSweepAsset))
+    );
+    assert_eq!(client.total_assets(), 1000);
+}
+
+// EIP-4626 rounding directions, checked at supply 3 / assets 10 where every preview lands
+// between two integers. With no offset the exchange rate is (supply + 1) / (assets + 1) = 4 / 11.
+mod rounding_tests {
+    use super::*;
+
+    fn setup_three_shares_ten_assets() -> TestSetup {
+        setup_with_mode(None)
+    }
+
+    fn setup_with_mode(mode: Option<RoundingMode>) -> TestSetup {
+        let setup = TestSetup::new();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+        let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+        client.initialize(
+            &setup.admin,
+            &setup.token_id,
+            &String::from_str(&setup.env, "Test Vault"),
+            &String::from_str(&setup.env, "TVAULT"),
+            &18,
+            &0,
+            &mode,
+        );
+        setup.initialize_token(1_000_000);
+        setup.mint_tokens(&setup.user, 1000);
+
+        setup.env.mock_all_auths();
+        client.deposit(&setup.user, &3, &setup.user);
+        token_client.transfer(&setup.user, &setup.vault_id, &7);
+        assert_eq!(client.total_supply(), 3);
+        assert_eq!(client.total_assets(), 10);
+        setup
+    }
+
+    #[test]
+    fn convert_to_shares_detailed_reports_rounding_loss() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        // 5 * 4 / 11 = 1.81; one share is worth 11 / 4 = 2.75, so 2.25 stays with the vault
+        assert_eq!(client.convert_to_shares_detailed(&5), (1, 2));
+        // 2 * 4 / 11 = 0.72; nothing is issued and the whole deposit stays
+        assert_eq!(client.convert_to_shares_detailed(&2), (0, 2));
+        // 11 * 4 / 11 = 4 exactly
+        assert_eq!(client.convert_to_shares_detailed(&11), (4, 0));
+        assert_eq!(client.convert_to_shares_detailed(&5).0, client.convert_to_shares(&5));
+    }
+
+    #[test]
+    fn preview_deposit_rounds_down() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        // 5 * 4 / 11 = 1.81
+        assert_eq!(client.preview_deposit(&5), 1);
+        // 2 * 4 / 11 = 0.72
+        assert_eq!(client.preview_deposit(&2), 0);
+    }
+
+    #[test]
+    fn preview_mint_rounds_up() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        // 1 * 11 / 4 = 2.75
+        assert_eq!(client.preview_mint(&1), 3);
+        // 3 * 11 / 4 = 8.25
+        assert_eq!(client.preview_mint(&3), 9);
+    }
+
+    #[test]
+    fn preview_withdraw_rounds_up() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        // 5 * 4 / 11 = 1.81
+        assert_eq!(client.preview_withdraw(&5), 2);
+        // 1 * 4 / 11 = 0.36; withdrawing any assets costs at least one share
+        assert_eq!(client.preview_withdraw(&1), 1);
+    }
+
+    #[test]
+    fn preview_redeem_rounds_down() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        // 1 * 11 / 4 = 2.75
+        assert_eq!(client.preview_redeem(&1), 2);
+        // 3 * 11 / 4 = 8.25
+        assert_eq!(client.preview_redeem(&3), 8);
+    }
+
+    #[test]
+    fn round_trips_never_profit() {
+        for mode in [None, Some(RoundingMode::Floor), Some(RoundingMode::Ceil), Some(RoundingMode::HalfUp)] {
+            let setup = setup_with_mode(mode);
+            let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+            let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+
+            for assets in 1..=20i128 {
+                let shares = client.preview_deposit(&assets);
+                assert!(client.preview_redeem(&shares) <= assets, "{mode:?}");
+                assert!(client.preview_withdraw(&assets) >= shares, "{mode:?}");
+            }
+            for shares in 1..=20i128 {
+                let assets = client.preview_mint(&shares);
+                assert!(client.preview_deposit(&assets) >= shares, "{mode:?}");
+            }
+
+            let before = token_client.balance(&setup.user);
+            for assets in [1, 1, 1, 1, 1, 5, 5] {
+                if let Ok(Ok(shares)) = client.try_deposit(&setup.user, &assets, &setup.user) {
+                    client.redeem(&setup.user, &shares, &setup.user, &setup.user);
+                }
+            }
+            assert!(token_client.balance(&setup.user) <= before, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn default_mode_favors_vault() {
+        let setup = setup_three_shares_ten_assets();
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        assert_eq!(client.rounding_mode(), None);
+        assert_eq!(client.preview_deposit(&5), 1);
+        assert_eq!(client.preview_mint(&1), 3);
+    }
+
+    #[test]
+    fn floor_mode_always_rounds_down() {
+        let setup = setup_with_mode(Some(RoundingMode::Floor));
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        assert_eq!(client.rounding_mode(), Some(RoundingMode::Floor));
+        // 5 * 4 / 11 = 1.81, 2 * 4 / 11 = 0.72, 1 * 4 / 11 = 0.36
+        assert_eq!(client.convert_to_shares(&5), 1);
+        assert_eq!(client.convert_to_shares(&2), 0);
+        assert_eq!(client.convert_to_shares(&1), 0);
+        // 1 * 11 / 4 = 2.75, 3 * 11 / 4 = 8.25
+        assert_eq!(client.convert_to_assets(&1), 2);
+        assert_eq!(client.convert_to_assets(&3), 8);
+        // Minting still rounds up
+        assert_eq!(client.preview_mint(&1), 3);
+        assert_eq!(client.preview_withdraw(&5), 2);
+    }
+
+    #[test]
+    fn ceil_mode_always_rounds_up() {
+        let setup = setup_with_mode(Some(RoundingMode::Ceil));
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        assert_eq!(client.convert_to_shares(&5), 2);
+        assert_eq!(client.convert_to_shares(&2), 1);
+        assert_eq!(client.convert_to_shares(&1), 1);
+        assert_eq!(client.convert_to_assets(&1), 3);
+        assert_eq!(client.convert_to_assets(&3), 9);
+        // Deposits and redemptions still round down
+        assert_eq!(client.preview_deposit(&5), 1);
+        assert_eq!(client.preview_redeem(&1), 2);
+    }
+
+    #[test]
+    fn half_up_mode_rounds_to_nearest() {
+        let setup = setup_with_mode(Some(RoundingMode::HalfUp));
+        let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+        assert_eq!(client.convert_to_shares(&5), 2);
+        assert_eq!(client.convert_to_shares(&2), 1);
+        assert_eq!(client.convert_to_shares(&1), 0);
+        assert_eq!(client.convert_to_assets(&1), 3);
+        assert_eq!(client.convert_to_assets(&3), 8);
+        assert_eq!(client.preview_deposit(&5), 1);
+        assert_eq!(client.preview_mint(&3), 9);
+    }
+}
+
+// Round-trip properties over a grid of vault states: converting either way and back, or
+// depositing and redeeming straight away, must never hand back more than went in.
+mod property_tests {
+    use super::*;
+
+    const SUPPLIES: [i128; 7] = [0, 1, 3, 7, 100, 999, 1_000_000];
+    const TOTALS: [i128; 6] = [0, 1, 2, 10, 1_000, 1_000_003];
+    const AMOUNTS: [i128; 8] = [1, 2, 3, 5, 17, 100, 1_001, 123_456_789];
+
+    fn vault_with_offset(decimals_offset: u32) -> TestSetup {
+        let setup = TestSetup::new();
+        setup.initialize_vault_with_offset("Test Vault", "TVAULT", 7, decimals_offset);
+        setup
+    }
+
+    #[test]
+    fn conversion_round_trips_never_create_value() {
+        for offset in [0, 3] {
+            let setup = vault_with_offset(offset);
+            let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+
+            for supply in SUPPLIES {
+                for total in TOTALS {
+                    for x in AMOUNTS {
+                        let shares = client.convert_to_shares_at(&x, &total, &supply);
+                        assert!(
+                            client.convert_to_assets_at(&shares, &total, &supply) <= x,
+                            "assets {x} at supply {supply}, total {total}, offset {offset}"
+                        );
+
+                        let assets = client.convert_to_assets_at(&x, &total, &supply);
+                        assert!(
+                            client.convert_to_shares_at(&assets, &total, &supply) <= x,
+                            "shares {x} at supply {supply}, total {total}, offset {offset}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deposit_then_redeem_never_profits() {
+        for (seed, donation) in [(1, 0), (1, 7), (3, 10), (1_000, 1), (1_000, 999)] {
+            let setup = vault_with_offset(0);
+            let client = VaultContractClient::new(&setup.env, &setup.vault_id);
+            let token_client = token::MockTokenClient::new(&setup.env, &setup.token_id);
+            setup.initialize_token(1_000_000);
+            setup.mint_tokens(&setup.user, 10_000);
+            setup.mint_tokens(&setup.user2, 10_000);
+
+            setup.env.mock_all_auths();
+            client.deposit(&setup.user, &seed, &setup.user);
+            if donation > 0 {
+                token_client.transfer(&setup.user, &setup.vault_id, &donation);
+            }
+
+            for assets in 1..=30i128 {
+                if client.preview_deposit(&assets) == 0 {
+                    continue;
+                }
+                let before = token_client.balance(&setup.user2);
+                let shares = client.deposit(&setup.user2, &assets, &setup.user2);
+                let returned = client.redeem(&setup.user2, &shares, &setup.user2, &setup.user2);
+                assert!(returned <= assets, "deposit {assets} after seed {seed}, donation {donation}");
+                assert!(token_client.balance(&setup.user2) <= before);
+
+                // Minting the same number of shares costs at least what they redeem for
+                let cost = client.mint(&setup.user2, &assets, &setup.user2);
+                let returned = client.redeem(&setup.user2, &assets, &setup.user2, &setup.user2);
+                assert!(returned <= cost, "mint {assets} after seed {seed}, donation {donation}");
+            }
+        }
+    }
+}